@@ -2,7 +2,8 @@
 set -e
 
 echo "Building i2pd-exporter for x86_64 Linux (GNU)..."
-docker build -t i2pd-exporter-build -f Dockerfile.build .
+docker build -t i2pd-exporter-build -f Dockerfile.build \
+  --build-arg GIT_SHA="$(git rev-parse --short=12 HEAD 2>/dev/null || echo unknown)" .
 
 echo "Extracting binary..."
 docker create --name temp-container i2pd-exporter-build
@@ -0,0 +1,48 @@
+// Embed the git revision as I2PD_EXPORTER_GIT_SHA for `version::GIT_SHA`, plus the compiler
+// version and cargo profile for `version::RUSTC_VERSION` / `version::BUILD_PROFILE`.
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    // HEAD moves on checkout; refs move on commit.
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let sha = std::env::var("GIT_SHA")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .or_else(git_sha)
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=I2PD_EXPORTER_GIT_SHA={}", sha.trim());
+
+    let rustc = rustc_version().unwrap_or_else(|| "unknown".to_string());
+    println!(
+        "cargo:rustc-env=I2PD_EXPORTER_RUSTC_VERSION={}",
+        rustc.trim()
+    );
+    // "debug" or "release"; custom profiles report the profile they inherit from.
+    let profile = std::env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string());
+    println!("cargo:rustc-env=I2PD_EXPORTER_BUILD_PROFILE={}", profile);
+}
+
+// `rustc 1.83.0 (90b35a623 2024-11-26)` -> `1.83.0`
+fn rustc_version() -> Option<String> {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let out = Command::new(rustc).arg("--version").output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(out.stdout).ok()?;
+    text.split_whitespace().nth(1).map(str::to_string)
+}
+
+fn git_sha() -> Option<String> {
+    let out = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    String::from_utf8(out.stdout).ok()
+}
@@ -0,0 +1,92 @@
+// HTTP Basic authentication for the metrics endpoint
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct BasicCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for BasicCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BasicCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl BasicCredentials {
+    // Check an `Authorization` header value against these credentials.
+    pub fn verify(&self, authorization: Option<&str>) -> bool {
+        let Some(value) = authorization else {
+            return false;
+        };
+        let Some((scheme, encoded)) = value.trim().split_once(' ') else {
+            return false;
+        };
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return false;
+        }
+        let Ok(decoded) = STANDARD.decode(encoded.trim()) else {
+            return false;
+        };
+        let Some(sep) = decoded.iter().position(|&b| b == b':') else {
+            return false;
+        };
+        let (user, pass) = (&decoded[..sep], &decoded[sep + 1..]);
+        // Evaluate both so timing does not reveal which half mismatched.
+        let user_ok = constant_time_eq(user, self.username.as_bytes());
+        let pass_ok = constant_time_eq(pass, self.password.as_bytes());
+        user_ok & pass_ok
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creds() -> BasicCredentials {
+        BasicCredentials {
+            username: "prom".to_string(),
+            password: "s3cret".to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_correct_credentials() {
+        // base64("prom:s3cret")
+        assert!(creds().verify(Some("Basic cHJvbTpzM2NyZXQ=")));
+        assert!(creds().verify(Some("basic cHJvbTpzM2NyZXQ=")));
+    }
+
+    #[test]
+    fn rejects_wrong_credentials() {
+        // base64("prom:wrong")
+        assert!(!creds().verify(Some("Basic cHJvbTp3cm9uZw==")));
+        // base64("other:s3cret")
+        assert!(!creds().verify(Some("Basic b3RoZXI6czNjcmV0")));
+        assert!(!creds().verify(Some("Bearer cHJvbTpzM2NyZXQ=")));
+        assert!(!creds().verify(Some("Basic !!!")));
+    }
+
+    #[test]
+    fn rejects_missing_credentials() {
+        assert!(!creds().verify(None));
+        assert!(!creds().verify(Some("")));
+    }
+
+    #[test]
+    fn debug_output_redacts_password() {
+        assert!(!format!("{:?}", creds()).contains("s3cret"));
+    }
+}
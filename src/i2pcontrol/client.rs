@@ -1,11 +1,19 @@
 // I2PControl client implementation
 
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use log::{debug, info, warn};
 use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
 
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 
-use super::rpc::rpc_call;
-use super::types::RouterInfoResult;
+use super::rpc::{rpc_batch, rpc_call, ErrorKind, RpcCallError, RpcReply};
+use super::targets::DEFAULT_JSONRPC_PATH;
+use super::types::{NetworkSettingResult, RouterInfoResult};
+use crate::metrics::ExporterCounters;
 
 const ROUTER_INFO_KEYS_BATCH_1: &[&str] = &[
     "i2p.router.status",              // Router status as string "1" or "0"
@@ -15,6 +23,7 @@ const ROUTER_INFO_KEYS_BATCH_1: &[&str] = &[
     "i2p.router.net.bw.inbound.15s",  // Request inbound bandwidth (15s avg, Bps)
     "i2p.router.net.bw.outbound.1s",  // Request outbound bandwidth (1s avg, Bps)
     "i2p.router.net.bw.outbound.15s", // Request outbound bandwidth (15s avg, Bps)
+    "i2p.router.net.bw.transit.1s",   // Request transit bandwidth (1s avg, Bps)
     "i2p.router.net.bw.transit.15s",  // Request transit bandwidth (15s avg, Bps)
     "i2p.router.net.status", // Request IPv4 network status code (0 OK, 1 Firewalled, 2 Unknown, 3 Proxy, 4 Mesh, 5 Stan)
     "i2p.router.net.status.v6", // Request IPv6 network status code (optional, same mapping)
@@ -41,6 +50,76 @@ const ROUTER_INFO_KEYS_BATCH_2: &[&str] = &[
     "i2p.router.net.total.transit.bytes",      // Request total transit bytes transmitted
 ];
 
+// Cheap, alert-critical subset served by /metrics-lite.
+const LITE_ROUTER_INFO_KEYS: &[&str] = &[
+    "i2p.router.status",
+    "i2p.router.uptime",
+    "i2p.router.net.bw.inbound.1s",
+    "i2p.router.net.bw.inbound.15s",
+    "i2p.router.net.bw.outbound.1s",
+    "i2p.router.net.bw.outbound.15s",
+    "i2p.router.net.bw.transit.1s",
+    "i2p.router.net.bw.transit.15s",
+    "i2p.router.net.status",
+    "i2p.router.net.status.v6",
+];
+
+// Readiness probes fetch just this key; results are shared for a moment to absorb probe bursts.
+const READY_CHECK_KEY: &str = "i2p.router.status";
+const READY_RESULT_TTL: Duration = Duration::from_secs(1);
+
+const NETWORK_SETTING_KEYS: &[&str] = &[
+    "i2p.router.net.bw.in",    // Configured inbound bandwidth limit (KBps)
+    "i2p.router.net.bw.out",   // Configured outbound bandwidth limit (KBps)
+    "i2p.router.net.bw.share", // Share of bandwidth offered to transit (percent)
+];
+
+// Keep this much of the budget for RouterInfo results; NetworkSetting is best-effort.
+const NETWORK_SETTING_RESERVE: Duration = Duration::from_millis(250);
+
+// Every key the exporter knows how to turn into metrics, in request order.
+pub fn default_router_info_keys() -> Vec<String> {
+    ROUTER_INFO_KEYS_BATCH_1
+        .iter()
+        .chain(ROUTER_INFO_KEYS_BATCH_2)
+        .map(|k| k.to_string())
+        .collect()
+}
+
+// Lite subset of the configured keys, so exclusions still apply.
+pub fn lite_router_info_keys(configured: &[String]) -> Vec<String> {
+    configured
+        .iter()
+        .filter(|k| LITE_ROUTER_INFO_KEYS.contains(&k.as_str()))
+        .cloned()
+        .collect()
+}
+
+pub fn is_known_router_info_key(key: &str) -> bool {
+    ROUTER_INFO_KEYS_BATCH_1.contains(&key) || ROUTER_INFO_KEYS_BATCH_2.contains(&key)
+}
+
+// Split the requested keys into the two RouterInfo calls; extra keys ride in the second one.
+fn router_info_batches(keys: &[String]) -> Vec<Vec<&str>> {
+    let (first, second): (Vec<&str>, Vec<&str>) = keys
+        .iter()
+        .map(String::as_str)
+        .partition(|k| ROUTER_INFO_KEYS_BATCH_1.contains(k));
+    [first, second]
+        .into_iter()
+        .filter(|batch| !batch.is_empty())
+        .collect()
+}
+
+// NetworkSetting treats any non-null value as a write, so reads must send JSON null.
+fn build_network_setting_params() -> Value {
+    let params = NETWORK_SETTING_KEYS
+        .iter()
+        .map(|key| ((*key).to_string(), Value::Null))
+        .collect();
+    Value::Object(params)
+}
+
 fn build_router_info_params(keys: &[&str]) -> Value {
     let mut params = serde_json::Map::new();
     for key in keys {
@@ -50,21 +129,319 @@ fn build_router_info_params(keys: &[&str]) -> Value {
     Value::Object(params)
 }
 
+// JSON-RPC "invalid params"; some routers answer unknown RouterInfo keys with it.
+const INVALID_PARAMS: i32 = -32602;
+
+// Keys of `batch` named in an invalid-params error message.
+fn rejected_keys<'a>(err: &RpcCallError, batch: &[&'a str]) -> Vec<&'a str> {
+    let RpcCallError::Rpc { code, message, .. } = err else {
+        return Vec::new();
+    };
+    if *code != INVALID_PARAMS {
+        return Vec::new();
+    }
+    // Whole tokens only: `i2p.router.net.status` must not match `i2p.router.net.status.v6`.
+    let named: Vec<&str> = message
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+        .map(|t| t.trim_matches('.'))
+        .collect();
+    batch
+        .iter()
+        .copied()
+        .filter(|k| named.contains(k))
+        .collect()
+}
+
+const RETRY_BASE_BACKOFF: Duration = Duration::from_millis(100);
+
+const SCRAPE_NONE: u8 = 0;
+const SCRAPE_OK: u8 = 1;
+const SCRAPE_FAILED: u8 = 2;
+
+// Scrape settings shared by every client, including per-target ones.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub max_scrape_timeout: Duration, // Hard cap for header-derived scrape timeout
+    pub cache_ttl: Duration,          // Reuse a RouterInfo result this long; zero disables
+    pub max_concurrent_scrapes: usize, // In-flight RouterInfo fetches allowed per target
+    pub rpc_retries: u32,             // Extra attempts after a transport-level RPC failure
+    pub rpc_call_timeout: Option<Duration>, // Per-call cap below the remaining scrape budget
+    pub max_response_bytes: usize,    // Larger I2PControl response bodies are refused
+    pub batch_rpc: bool,              // Send a scrape's calls as one JSON-RPC batch POST
+    pub router_info_keys: Vec<String>, // Keys requested from RouterInfo
+    pub jsonrpc_path: String,         // Appended to target addresses to form the JSON-RPC URL
+}
+
+// Same values as the CLI defaults.
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            max_scrape_timeout: Duration::from_secs(120),
+            cache_ttl: Duration::ZERO,
+            max_concurrent_scrapes: 4,
+            rpc_retries: 2,
+            rpc_call_timeout: None,
+            max_response_bytes: 1 << 20,
+            batch_rpc: false,
+            router_info_keys: default_router_info_keys(),
+            jsonrpc_path: DEFAULT_JSONRPC_PATH.to_string(),
+        }
+    }
+}
+
 // Holds shared state for the application, including the API client,
 // and scrape configuration.
 pub struct I2pControlClient {
     pub api_client: reqwest::Client, // HTTP client for making API requests
     pub api_url: String,             // Full URL for the I2PControl JSON-RPC endpoint
-    pub max_scrape_timeout: Duration, // Hard cap for header-derived scrape timeout
+    pub target_address: String,      // Credential-free address for the target_info metric
+    pub options: ClientOptions,
+    last_scrape: AtomicU8, // Outcome of the most recent /metrics scrape
+    last_response_bytes: AtomicU64, // Body bytes of the last successful fetch
+    counters: ExporterCounters, // Self-metrics that outlive each per-scrape registry
+    cached: Mutex<Option<(Instant, RouterInfoResult)>>,
+    last_success: Mutex<Option<Instant>>, // Completion of the last successful RouterInfo fetch
+    routerinfo_duration: Mutex<Option<Duration>>, // Time spent in the last successful RouterInfo calls
+    last_router_version: Mutex<Option<String>>,   // Version reported by the last full fetch
+    scrape_permits: Semaphore,
+    batch_unsupported: AtomicBool, // Set once the router rejects a JSON-RPC batch
+    small_timeout_warned: AtomicBool, // A too-small scrape budget has been logged
+    dropped_keys: Mutex<Vec<String>>, // RouterInfo keys the router rejected; no longer requested
+    ready_check: tokio::sync::Mutex<Option<(Instant, bool)>>,
 }
 
 impl I2pControlClient {
-    // Creates a new AppState instance.
-    pub fn new(api_client: reqwest::Client, api_url: String, max_scrape_timeout: Duration) -> Self {
+    // Creates a new AppState instance; `counters` are shared with the HTTP client's connector.
+    pub fn new(
+        api_client: reqwest::Client,
+        api_url: String,
+        options: ClientOptions,
+        counters: ExporterCounters,
+    ) -> Self {
         I2pControlClient {
             api_client,
+            target_address: super::targets::display_address(
+                api_url
+                    .strip_suffix(&super::targets::jsonrpc_url("", &options.jsonrpc_path))
+                    .unwrap_or(&api_url),
+            ),
             api_url,
-            max_scrape_timeout,
+            last_scrape: AtomicU8::new(SCRAPE_NONE),
+            last_response_bytes: AtomicU64::new(0),
+            counters,
+            cached: Mutex::new(None),
+            last_success: Mutex::new(None),
+            routerinfo_duration: Mutex::new(None),
+            last_router_version: Mutex::new(None),
+            scrape_permits: Semaphore::new(options.max_concurrent_scrapes),
+            batch_unsupported: AtomicBool::new(false),
+            small_timeout_warned: AtomicBool::new(false),
+            dropped_keys: Mutex::new(Vec::new()),
+            ready_check: tokio::sync::Mutex::new(None),
+            options,
+        }
+    }
+
+    // Wait for a slot to talk to the router; the permit is released on drop.
+    pub async fn acquire_scrape_permit(&self) -> SemaphorePermit<'_> {
+        self.scrape_permits
+            .acquire()
+            .await
+            .expect("scrape semaphore is never closed")
+    }
+
+    // Return the last RouterInfo result if it is younger than the cache TTL.
+    pub fn cached_router_info(&self) -> Option<RouterInfoResult> {
+        if self.options.cache_ttl.is_zero() {
+            return None;
+        }
+        let cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        cached
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < self.options.cache_ttl)
+            .map(|(_, data)| data.clone())
+    }
+
+    pub fn store_router_info(&self, data: &RouterInfoResult) {
+        if self.options.cache_ttl.is_zero() {
+            return;
+        }
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        *cached = Some((Instant::now(), data.clone()));
+    }
+
+    // Record the outcome of a /metrics scrape for the health endpoint.
+    pub fn record_scrape_result(&self, success: bool) {
+        let v = if success { SCRAPE_OK } else { SCRAPE_FAILED };
+        self.last_scrape.store(v, Ordering::Relaxed);
+    }
+
+    // None until the first scrape completes.
+    pub fn last_scrape_succeeded(&self) -> Option<bool> {
+        match self.last_scrape.load(Ordering::Relaxed) {
+            SCRAPE_OK => Some(true),
+            SCRAPE_FAILED => Some(false),
+            _ => None,
+        }
+    }
+
+    // Live reachability check; concurrent callers wait for one probe and share its result.
+    pub async fn check_ready(&self, budget: Duration) -> bool {
+        let mut last = self.ready_check.lock().await;
+        if let Some((at, ready)) = *last {
+            if at.elapsed() < READY_RESULT_TTL {
+                return ready;
+            }
+        }
+        let keys = [READY_CHECK_KEY.to_string()];
+        let probe = self.fetch_router_info_keys(budget, &keys, false);
+        let ready = match tokio::time::timeout(budget, probe).await {
+            Ok(Ok(_)) => true,
+            Ok(Err(err)) => {
+                warn!("Readiness check failed: {}", err);
+                false
+            }
+            Err(_elapsed) => {
+                warn!(
+                    "Readiness check timed out after {:.3}s",
+                    budget.as_secs_f64()
+                );
+                false
+            }
+        };
+        *last = Some((Instant::now(), ready));
+        ready
+    }
+
+    // None until a RouterInfo fetch has succeeded.
+    pub fn seconds_since_last_success(&self) -> Option<f64> {
+        let last = self.last_success.lock().unwrap_or_else(|e| e.into_inner());
+        last.map(|at| at.elapsed().as_secs_f64())
+    }
+
+    // None until RouterInfo has answered once.
+    pub fn last_routerinfo_seconds(&self) -> Option<f64> {
+        let last = self
+            .routerinfo_duration
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        last.map(|d| d.as_secs_f64())
+    }
+
+    fn record_routerinfo_duration(&self, elapsed: Duration) {
+        *self
+            .routerinfo_duration
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(elapsed);
+    }
+
+    pub fn last_response_bytes(&self) -> u64 {
+        self.last_response_bytes.load(Ordering::Relaxed)
+    }
+
+    // Count scrapes rejected for lacking X-Prometheus-Scrape-Timeout-Seconds.
+    pub fn record_missing_timeout_header(&self) {
+        self.counters.missing_timeout_headers.inc();
+    }
+
+    // True only the first time, so a misconfigured job logs once instead of every scrape.
+    pub fn note_small_timeout(&self) -> bool {
+        !self.small_timeout_warned.swap(true, Ordering::Relaxed)
+    }
+
+    pub fn observe_scrape_duration(&self, seconds: f64) {
+        self.counters.scrape_durations.observe(seconds);
+    }
+
+    // Shared handles; registering them in a per-scrape registry exposes the running totals.
+    pub fn counters(&self) -> ExporterCounters {
+        self.counters.clone()
+    }
+
+    // `keys` minus those the router has rejected before.
+    fn requestable_keys(&self, keys: &[String]) -> Vec<String> {
+        let dropped = self.dropped_keys.lock().unwrap_or_else(|e| e.into_inner());
+        keys.iter()
+            .filter(|k| !dropped.contains(k))
+            .cloned()
+            .collect()
+    }
+
+    // Remember and return the keys named by an invalid-params error (empty if none).
+    fn drop_rejected_keys<'a>(&self, err: &RpcCallError, batch: &[&'a str]) -> Vec<&'a str> {
+        let rejected = rejected_keys(err, batch);
+        if !rejected.is_empty() {
+            warn!(
+                "Router rejected RouterInfo key(s) {}; no longer requesting them",
+                rejected.join(", ")
+            );
+            let mut dropped = self.dropped_keys.lock().unwrap_or_else(|e| e.into_inner());
+            dropped.extend(rejected.iter().map(|k| k.to_string()));
+        }
+        rejected
+    }
+
+    // Time allowed for the next call: what is left of the scrape, capped by the per-call limit.
+    // The flag reports whether the cap, not the deadline, is the binding limit.
+    fn call_timeout(&self, deadline: Instant) -> (Duration, bool) {
+        let rem = deadline.saturating_duration_since(Instant::now());
+        match self.options.rpc_call_timeout {
+            Some(cap) if cap < rem => (cap, true),
+            _ => (rem, false),
+        }
+    }
+
+    // One RPC call, retrying connection-level failures while the deadline allows.
+    async fn call_with_retries<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+        deadline: Instant,
+    ) -> Result<RpcReply<T>, RpcCallError> {
+        let mut attempt = 0u32;
+        loop {
+            let (rem, capped) = self.call_timeout(deadline);
+            self.counters.rpc_requests.inc();
+            let result = rpc_call::<T>(
+                &self.api_client,
+                &self.api_url,
+                method,
+                params.clone(),
+                rem,
+                self.options.max_response_bytes,
+            )
+            .await;
+            if let Err(RpcCallError::Transport(e)) = &result {
+                if e.is_connect() && e.is_timeout() {
+                    warn!("{} connect to I2PControl timed out: {}", method, e);
+                }
+            }
+            // RPC-level and decode errors are deterministic; only transport failures are retried.
+            // A timeout only leaves budget for another attempt when the per-call cap or the
+            // connect timeout cut it short.
+            let err = match result {
+                Err(RpcCallError::Transport(e))
+                    if (!e.is_timeout() || capped || e.is_connect())
+                        && attempt < self.options.rpc_retries =>
+                {
+                    e
+                }
+                other => return other,
+            };
+            let backoff = RETRY_BASE_BACKOFF * 2u32.pow(attempt);
+            if Instant::now() + backoff >= deadline {
+                return Err(RpcCallError::Transport(err));
+            }
+            attempt += 1;
+            warn!(
+                "{} transport error (retry {}/{} in {}ms): {}",
+                method,
+                attempt,
+                self.options.rpc_retries,
+                backoff.as_millis(),
+                err
+            );
+            tokio::time::sleep(backoff).await;
         }
     }
 
@@ -73,13 +450,91 @@ impl I2pControlClient {
         &self,
         overall_timeout: Duration,
     ) -> Result<RouterInfoResult, Box<dyn std::error::Error + Send + Sync>> {
+        let data = self
+            .fetch_router_info_keys(overall_timeout, &self.options.router_info_keys, true)
+            .await?;
+        if let Some(version) = &data.router_version {
+            self.note_router_version(version);
+        }
+        Ok(data)
+    }
+
+    // Count and log upgrades/rollbacks; the first version seen is just remembered.
+    fn note_router_version(&self, version: &str) {
+        let mut last = self
+            .last_router_version
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(previous) = last.as_deref().filter(|prev| *prev != version) {
+            info!("Router version changed from {} to {}", previous, version);
+            self.counters.router_version_changes.inc();
+        }
+        if last.as_deref() != Some(version) {
+            *last = Some(version.to_string());
+        }
+    }
+
+    // Raw RouterInfo JSON for the configured keys, batches merged into one object.
+    pub async fn fetch_router_info_raw(
+        &self,
+        overall_timeout: Duration,
+    ) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
         let deadline = Instant::now() + overall_timeout;
+        let mut combined = serde_json::Map::new();
+        let keys = self.requestable_keys(&self.options.router_info_keys);
+        for keys in router_info_batches(&keys) {
+            let reply = self
+                .call_with_retries::<Value>("RouterInfo", build_router_info_params(&keys), deadline)
+                .await?;
+            if let Value::Object(map) = reply.result {
+                combined.extend(map);
+            }
+        }
+        Ok(Value::Object(combined))
+    }
+
+    // Fetch only `keys`, optionally followed by the best-effort NetworkSetting read.
+    pub async fn fetch_router_info_keys(
+        &self,
+        overall_timeout: Duration,
+        keys: &[String],
+        network_settings: bool,
+    ) -> Result<RouterInfoResult, Box<dyn std::error::Error + Send + Sync>> {
+        let deadline = Instant::now() + overall_timeout;
+        let batched = if self.options.batch_rpc && !self.batch_unsupported.load(Ordering::Relaxed) {
+            let keys = self.requestable_keys(keys);
+            self.fetch_batched(&keys, network_settings, deadline).await
+        } else {
+            None
+        };
+        let (combined, response_bytes) = match batched {
+            Some(result) => result?,
+            None => {
+                // Re-read: a failed batch may just have dropped rejected keys.
+                let keys = self.requestable_keys(keys);
+                self.fetch_sequential(&keys, network_settings, deadline)
+                    .await?
+            }
+        };
+
+        self.last_response_bytes
+            .store(response_bytes as u64, Ordering::Relaxed);
+        *self.last_success.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+        Ok(combined)
+    }
+
+    // One POST per RouterInfo batch, then NetworkSetting if the budget allows.
+    async fn fetch_sequential(
+        &self,
+        keys: &[String],
+        network_settings: bool,
+        deadline: Instant,
+    ) -> Result<(RouterInfoResult, usize), Box<dyn std::error::Error + Send + Sync>> {
         let mut combined = RouterInfoResult::default();
+        let mut response_bytes = 0usize;
+        let started = Instant::now();
 
-        for (batch_idx, keys) in [ROUTER_INFO_KEYS_BATCH_1, ROUTER_INFO_KEYS_BATCH_2]
-            .iter()
-            .enumerate()
-        {
+        for (batch_idx, keys) in router_info_batches(keys).iter().enumerate() {
             let now = Instant::now();
             let rem = if now >= deadline {
                 Duration::from_millis(0)
@@ -96,21 +551,239 @@ impl I2pControlClient {
                 )
                 .into());
             }
-            let params = build_router_info_params(keys);
+            // Retry without keys the router names as invalid; the batch shrinks every time.
+            let mut keys = keys.clone();
+            let reply = loop {
+                let params = build_router_info_params(&keys);
+                match self
+                    .call_with_retries::<RouterInfoResult>("RouterInfo", params, deadline)
+                    .await
+                {
+                    Ok(reply) => break Some(reply),
+                    Err(err) => {
+                        let rejected = self.drop_rejected_keys(&err, &keys);
+                        if rejected.is_empty() {
+                            return Err(Box::new(err));
+                        }
+                        keys.retain(|k| !rejected.contains(k));
+                        if keys.is_empty() {
+                            break None;
+                        }
+                    }
+                }
+            };
 
-            let data = rpc_call::<RouterInfoResult>(
-                &self.api_client,
-                &self.api_url,
-                "RouterInfo",
-                params,
-                rem,
-            )
-            .await
-            .map_err(|err| -> Box<dyn std::error::Error + Send + Sync> { Box::new(err) })?;
+            if let Some(reply) = reply {
+                response_bytes += reply.body_bytes;
+                self.counters
+                    .routerinfo_response_bytes
+                    .observe(reply.body_bytes as f64);
+                combined.merge_from(reply.result);
+                if reply.clock_offset_seconds.is_some() {
+                    combined.clock_offset_seconds = reply.clock_offset_seconds;
+                }
+            }
+        }
+        self.record_routerinfo_duration(started.elapsed());
 
-            combined.merge_from(data);
+        // Bandwidth limits are optional extras; never fail a scrape over them.
+        if let Some(ns_deadline) = network_setting_deadline(network_settings, deadline) {
+            match self
+                .call_with_retries::<NetworkSettingResult>(
+                    "NetworkSetting",
+                    build_network_setting_params(),
+                    ns_deadline,
+                )
+                .await
+            {
+                Ok(reply) => {
+                    response_bytes += reply.body_bytes;
+                    combined.merge_from(reply.result.into());
+                }
+                Err(err) => debug!(
+                    "NetworkSetting unavailable; skipping bandwidth limits: {}",
+                    err
+                ),
+            }
         }
 
-        Ok(combined)
+        Ok((combined, response_bytes))
+    }
+
+    // Every call of a scrape in one JSON-RPC batch POST. None means the batch itself failed
+    // and the caller should fall back to sequential calls.
+    async fn fetch_batched(
+        &self,
+        keys: &[String],
+        network_settings: bool,
+        deadline: Instant,
+    ) -> Option<Result<(RouterInfoResult, usize), RpcCallError>> {
+        let mut calls: Vec<(&str, Value)> = router_info_batches(keys)
+            .iter()
+            .map(|keys| ("RouterInfo", build_router_info_params(keys)))
+            .collect();
+        let with_network_setting = network_setting_deadline(network_settings, deadline).is_some();
+        if with_network_setting {
+            calls.push(("NetworkSetting", build_network_setting_params()));
+        }
+
+        let started = Instant::now();
+        let (rem, _) = self.call_timeout(deadline);
+        self.counters.rpc_requests.inc();
+        let reply = match rpc_batch(
+            &self.api_client,
+            &self.api_url,
+            &calls,
+            rem,
+            self.options.max_response_bytes,
+        )
+        .await
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                // A malformed or rejected batch will not get better; transport failures might.
+                let deterministic =
+                    matches!(err, RpcCallError::Decode { .. } | RpcCallError::Http { .. })
+                        && err.kind() != ErrorKind::Auth;
+                if deterministic && !self.batch_unsupported.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "JSON-RPC batch rejected; using sequential calls from now on: {}",
+                        err
+                    );
+                } else {
+                    debug!("JSON-RPC batch failed; retrying sequentially: {}", err);
+                }
+                return None;
+            }
+        };
+
+        let mut outcomes = reply.result.into_iter();
+        let network_setting = if with_network_setting {
+            outcomes.next_back()
+        } else {
+            None
+        };
+        let mut combined = RouterInfoResult::default();
+        for (outcome, keys) in outcomes.zip(router_info_batches(keys)) {
+            match outcome.into_result::<RouterInfoResult>("RouterInfo") {
+                Ok(result) => combined.merge_from(result),
+                // Sequential calls retry without the dropped keys.
+                Err(err) if !self.drop_rejected_keys(&err, &keys).is_empty() => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        if let Some(outcome) = network_setting {
+            match outcome.into_result::<NetworkSettingResult>("NetworkSetting") {
+                Ok(result) => combined.merge_from(result.into()),
+                Err(err) => debug!(
+                    "NetworkSetting unavailable; skipping bandwidth limits: {}",
+                    err
+                ),
+            }
+        }
+        combined.clock_offset_seconds = reply.clock_offset_seconds;
+        // RouterInfo and NetworkSetting share one body here; it is observed as a whole.
+        self.counters
+            .routerinfo_response_bytes
+            .observe(reply.body_bytes as f64);
+        // NetworkSetting rides in the same POST, so it cannot be timed separately here.
+        self.record_routerinfo_duration(started.elapsed());
+        Some(Ok((combined, reply.body_bytes)))
+    }
+}
+
+// NetworkSetting only runs while NETWORK_SETTING_RESERVE of the budget is left.
+fn network_setting_deadline(network_settings: bool, deadline: Instant) -> Option<Instant> {
+    deadline
+        .checked_sub(NETWORK_SETTING_RESERVE)
+        .filter(|d| network_settings && *d > Instant::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_with_ttl(cache_ttl: Duration) -> I2pControlClient {
+        I2pControlClient::new(
+            reqwest::Client::new(),
+            "http://127.0.0.1:1/jsonrpc".to_string(),
+            ClientOptions {
+                cache_ttl,
+                rpc_retries: 0,
+                ..ClientOptions::default()
+            },
+            ExporterCounters::default(),
+        )
+    }
+
+    #[test]
+    fn rejected_keys_match_whole_key_names_only() {
+        let err = RpcCallError::Rpc {
+            code: INVALID_PARAMS,
+            message: "unknown key 'i2p.router.net.status.v6'.".to_string(),
+            method: "RouterInfo".to_string(),
+        };
+        let batch = ["i2p.router.net.status", "i2p.router.net.status.v6"];
+        assert_eq!(
+            rejected_keys(&err, &batch),
+            vec!["i2p.router.net.status.v6"]
+        );
+
+        let other = RpcCallError::Rpc {
+            code: -32600,
+            message: "i2p.router.net.status".to_string(),
+            method: "RouterInfo".to_string(),
+        };
+        assert!(rejected_keys(&other, &batch).is_empty());
+    }
+
+    #[test]
+    fn batches_follow_known_split_and_skip_empty() {
+        let all = default_router_info_keys();
+        let batches = router_info_batches(&all);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0], ROUTER_INFO_KEYS_BATCH_1);
+        assert_eq!(batches[1], ROUTER_INFO_KEYS_BATCH_2);
+
+        let keys = vec![
+            "i2p.router.netdb.knownpeers".to_string(),
+            "i2p.router.custom".to_string(),
+        ];
+        assert_eq!(
+            router_info_batches(&keys),
+            vec![vec!["i2p.router.netdb.knownpeers", "i2p.router.custom"]]
+        );
+    }
+
+    #[test]
+    fn lite_keys_respect_configured_exclusions() {
+        let mut configured = default_router_info_keys();
+        assert_eq!(lite_router_info_keys(&configured), LITE_ROUTER_INFO_KEYS);
+
+        configured.retain(|k| k != "i2p.router.net.status.v6");
+        let lite = lite_router_info_keys(&configured);
+        assert_eq!(lite.len(), LITE_ROUTER_INFO_KEYS.len() - 1);
+        assert!(!lite.iter().any(|k| k == "i2p.router.net.status.v6"));
+    }
+
+    #[test]
+    fn cache_disabled_with_zero_ttl() {
+        let client = client_with_ttl(Duration::ZERO);
+        client.store_router_info(&RouterInfoResult::default());
+        assert!(client.cached_router_info().is_none());
+    }
+
+    #[test]
+    fn cache_returns_stored_result_within_ttl() {
+        let client = client_with_ttl(Duration::from_secs(60));
+        assert!(client.cached_router_info().is_none());
+
+        let data = RouterInfoResult {
+            router_version: Some("2.49.0".to_string()),
+            ..RouterInfoResult::default()
+        };
+        client.store_router_info(&data);
+        let cached = client.cached_router_info().unwrap();
+        assert_eq!(cached.router_version.as_deref(), Some("2.49.0"));
     }
 }
@@ -2,7 +2,9 @@
 
 pub mod client;
 pub mod rpc;
+pub mod targets;
 pub mod types;
 
 // Re-export commonly used types
-pub use client::I2pControlClient;
+pub use client::{ClientOptions, I2pControlClient};
+pub use targets::TargetClients;
@@ -0,0 +1,499 @@
+// Per-target I2PControl clients for the multi-target exporter pattern
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use prometheus_client::metrics::counter::Counter;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use thiserror::Error;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::client::{ClientOptions, I2pControlClient};
+use crate::metrics::ExporterCounters;
+use crate::server::RouteOptions;
+use crate::version;
+
+// Bounds memory when scrapers send many distinct targets; the least recently used is evicted.
+const MAX_CACHED_TARGETS: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum TargetError {
+    #[error("invalid target '{target}': {reason}")]
+    Invalid { target: String, reason: String },
+
+    #[error("failed to build HTTP client for target '{target}': {error}")]
+    Client {
+        target: String,
+        error: reqwest::Error,
+    },
+}
+
+// Whether the URL host is `localhost` or a loopback IP literal.
+pub fn is_loopback_target(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
+}
+
+// Loopback hosts are trusted with self-signed certs unless strict TLS is requested.
+pub fn allow_invalid_certs(url: &reqwest::Url, tls_insecure: bool, strict_tls: bool) -> bool {
+    tls_insecure || (!strict_tls && is_loopback_target(url))
+}
+
+// Read a PEM CA certificate for I2PControl TLS. reqwest defers PEM parsing to connect time
+// with rustls, so the file is checked here to fail at startup instead.
+pub fn load_ca_cert(path: &Path) -> Result<reqwest::Certificate, String> {
+    let pem = std::fs::read(path)
+        .map_err(|e| format!("Cannot read I2PCONTROL_TLS_CA '{}': {}", path.display(), e))?;
+    let certs = CertificateDer::pem_slice_iter(&pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            format!(
+                "Invalid PEM in I2PCONTROL_TLS_CA '{}': {}",
+                path.display(),
+                e
+            )
+        })?;
+    if certs.is_empty() {
+        return Err(format!(
+            "I2PCONTROL_TLS_CA '{}' contains no certificates",
+            path.display()
+        ));
+    }
+    reqwest::Certificate::from_pem(&pem).map_err(|e| {
+        format!(
+            "Invalid PEM in I2PCONTROL_TLS_CA '{}': {}",
+            path.display(),
+            e
+        )
+    })
+}
+
+// Prefix selecting a Unix domain socket I2PControl endpoint, e.g. `unix:/run/i2pd/i2pcontrol.sock`.
+pub const UNIX_ADDRESS_PREFIX: &str = "unix:";
+
+// Placeholder base URL for socket requests; only the path and Host header are used.
+const UNIX_SOCKET_BASE_URL: &str = "http://localhost";
+
+// Transport settings shared by the default and per-target HTTP clients.
+#[derive(Debug, Clone, Default)]
+pub struct HttpSettings {
+    pub proxy: Option<String>, // socks5://, socks5h:// or http:// proxy URL
+    pub unix_socket: Option<PathBuf>, // Connect over this socket instead of TCP
+    pub user_agent: Option<String>, // Overrides the default `i2pd-exporter/<version>`
+    pub strict_tls: bool,      // Loopback targets get no self-signed exemption
+    pub ca_cert: Option<reqwest::Certificate>, // Extra trust root for self-signed routers
+    pub http2: bool,           // Negotiate HTTP/2 via ALPN instead of forcing HTTP/1.1
+    pub connect_timeout: Option<Duration>, // Fail unreachable targets before the request timeout
+}
+
+// Socket path for a `unix:` I2PControl address, or None for http(s) URLs.
+pub fn unix_socket_path(address: &str) -> Option<PathBuf> {
+    address
+        .strip_prefix(UNIX_ADDRESS_PREFIX)
+        .map(|path| PathBuf::from(path.trim()))
+}
+
+// JSON-RPC URL for an I2PControl address, accepting both URLs and `unix:` paths.
+pub fn endpoint_url(address: &str, path: &str) -> String {
+    if unix_socket_path(address).is_some() {
+        jsonrpc_url(UNIX_SOCKET_BASE_URL, path)
+    } else {
+        jsonrpc_url(address, path)
+    }
+}
+
+// Connector wrapper counting established connections. Requests on a pooled keep-alive
+// connection never reach the connector, so this counts new connections only.
+#[derive(Clone)]
+struct CountConnections<S> {
+    inner: S,
+    created: Counter,
+}
+
+impl<S, R> Service<R> for CountConnections<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let created = self.created.clone();
+        let connecting = self.inner.call(req);
+        Box::pin(async move {
+            let conn = connecting.await?;
+            created.inc();
+            Ok(conn)
+        })
+    }
+}
+
+#[derive(Clone)]
+struct CountConnectionsLayer(Counter);
+
+impl<S> Layer<S> for CountConnectionsLayer {
+    type Service = CountConnections<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CountConnections {
+            inner,
+            created: self.0.clone(),
+        }
+    }
+}
+
+// Build the HTTP client used for I2PControl requests; new connections are counted in
+// `connections`.
+pub fn build_http_client(
+    allow_invalid_certs: bool,
+    settings: &HttpSettings,
+    connections: &Counter,
+) -> reqwest::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .connector_layer(CountConnectionsLayer(connections.clone()))
+        .danger_accept_invalid_certs(allow_invalid_certs)
+        .user_agent(
+            settings
+                .user_agent
+                .clone()
+                .unwrap_or_else(|| format!("i2pd-exporter/{}", version::VERSION)),
+        );
+    // HTTP/1.1 stays the default; rpc_call sends fixed-length bodies, so h2 is opt-in only
+    // because some I2PControl servers have never been tested with it.
+    if !settings.http2 {
+        builder = builder.http1_only();
+    }
+    if let Some(timeout) = settings.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(cert) = &settings.ca_cert {
+        builder = builder.add_root_certificate(cert.clone());
+    }
+    if let Some(proxy) = &settings.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    #[cfg(unix)]
+    if let Some(path) = &settings.unix_socket {
+        builder = builder.unix_socket(path.clone());
+    }
+    builder.build()
+}
+
+// Address as shown in metric labels: credentials stripped, no trailing slash.
+pub fn display_address(address: &str) -> String {
+    let address = address.trim();
+    if address.starts_with(UNIX_ADDRESS_PREFIX) {
+        return address.to_string();
+    }
+    match reqwest::Url::parse(address) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.as_str().trim_end_matches('/').to_string()
+        }
+        Err(_) if address.contains('@') => "<redacted>".to_string(),
+        Err(_) => address.trim_end_matches('/').to_string(),
+    }
+}
+
+pub const DEFAULT_JSONRPC_PATH: &str = "/jsonrpc";
+
+// Join a base address and JSON-RPC path with exactly one slash between them.
+pub fn jsonrpc_url(base: &str, path: &str) -> String {
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+// Parse a `?target=` value, rejecting plaintext to remote hosts unless TLS-insecure is set.
+pub fn parse_target(target: &str, tls_insecure: bool) -> Result<reqwest::Url, TargetError> {
+    let invalid = |reason: &str| TargetError::Invalid {
+        target: target.to_string(),
+        reason: reason.to_string(),
+    };
+    let url = reqwest::Url::parse(target).map_err(|e| invalid(&e.to_string()))?;
+    if url.host_str().is_none() {
+        return Err(invalid("missing host"));
+    }
+    match url.scheme() {
+        "https" => Ok(url),
+        "http" if tls_insecure || is_loopback_target(&url) => Ok(url),
+        "http" => Err(invalid(
+            "plain http is only allowed for loopback targets unless TLS-insecure is set",
+        )),
+        other => Err(invalid(&format!("unsupported scheme '{}'", other))),
+    }
+}
+
+// Everything a config reload replaces at once.
+struct Current {
+    default: Arc<I2pControlClient>,
+    tls_insecure: bool,
+    http: HttpSettings,
+    options: ClientOptions,
+}
+
+impl Current {
+    fn new(default: Arc<I2pControlClient>, tls_insecure: bool, http: HttpSettings) -> Self {
+        // `?target=` overrides are URLs; the default target's socket must not capture them.
+        let http = HttpSettings {
+            unix_socket: None,
+            ..http
+        };
+        Current {
+            options: default.options.clone(),
+            default,
+            tls_insecure,
+            http,
+        }
+    }
+}
+
+// `?target=` clients with a use stamp for least-recently-used eviction.
+#[derive(Default)]
+struct TargetCache {
+    clients: HashMap<String, (Arc<I2pControlClient>, u64)>,
+    uses: u64,
+}
+
+impl TargetCache {
+    fn get(&mut self, key: &str) -> Option<Arc<I2pControlClient>> {
+        self.uses += 1;
+        let (client, last_used) = self.clients.get_mut(key)?;
+        *last_used = self.uses;
+        Some(client.clone())
+    }
+
+    fn insert(&mut self, key: String, client: Arc<I2pControlClient>) {
+        if self.clients.len() >= MAX_CACHED_TARGETS {
+            let oldest = self
+                .clients
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.clients.remove(&oldest);
+            }
+        }
+        self.clients.insert(key, (client, self.uses));
+    }
+}
+
+// Default client plus lazily created clients for `?target=` overrides.
+pub struct TargetClients {
+    current: Mutex<Current>,
+    cache: Mutex<TargetCache>,
+    // Read per request so a reload can swap auth and timeout settings in place.
+    route_options: Mutex<Arc<RouteOptions>>,
+}
+
+impl TargetClients {
+    pub fn new(default: Arc<I2pControlClient>, tls_insecure: bool, http: HttpSettings) -> Self {
+        TargetClients {
+            current: Mutex::new(Current::new(default, tls_insecure, http)),
+            cache: Mutex::new(TargetCache::default()),
+            route_options: Mutex::new(Arc::new(RouteOptions::default())),
+        }
+    }
+
+    fn current(&self) -> MutexGuard<'_, Current> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn default_client(&self) -> Arc<I2pControlClient> {
+        self.current().default.clone()
+    }
+
+    // Swap in a reloaded default client; `?target=` clients are rebuilt on next use.
+    // Scrapes already running finish on the client they started with.
+    pub fn replace(&self, default: Arc<I2pControlClient>, tls_insecure: bool, http: HttpSettings) {
+        let mut current = self.current();
+        *current = Current::new(default, tls_insecure, http);
+        self.cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clients
+            .clear();
+    }
+
+    pub fn route_options(&self) -> Arc<RouteOptions> {
+        self.route_options
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // Swap the options used by requests from now on; requests in flight keep theirs.
+    pub fn replace_route_options(&self, options: RouteOptions) {
+        *self.route_options.lock().unwrap_or_else(|e| e.into_inner()) = Arc::new(options);
+    }
+
+    // Resolve the client for a scrape; `None` selects the configured default target.
+    pub fn resolve(&self, target: Option<&str>) -> Result<Arc<I2pControlClient>, TargetError> {
+        let current = self.current();
+        let Some(target) = target.filter(|t| !t.is_empty()) else {
+            return Ok(current.default.clone());
+        };
+        let url = parse_target(target, current.tls_insecure)?;
+        let key = url.as_str().trim_end_matches('/').to_string();
+
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(client) = cache.get(&key) {
+            return Ok(client);
+        }
+
+        let allow_invalid_certs =
+            allow_invalid_certs(&url, current.tls_insecure, current.http.strict_tls);
+        let counters = ExporterCounters::default();
+        let http = build_http_client(
+            allow_invalid_certs,
+            &current.http,
+            &counters.connections_created,
+        )
+        .map_err(|error| TargetError::Client {
+            target: target.to_string(),
+            error,
+        })?;
+        let client = Arc::new(I2pControlClient::new(
+            http,
+            jsonrpc_url(&key, &current.options.jsonrpc_path),
+            current.options.clone(),
+            counters,
+        ));
+        cache.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_address_maps_to_socket_path() {
+        assert_eq!(
+            unix_socket_path("unix:/run/i2pd/i2pcontrol.sock"),
+            Some(PathBuf::from("/run/i2pd/i2pcontrol.sock"))
+        );
+        assert_eq!(unix_socket_path("https://127.0.0.1:7650"), None);
+        assert_eq!(
+            endpoint_url("unix:/run/i2pd/i2pcontrol.sock", DEFAULT_JSONRPC_PATH),
+            "http://localhost/jsonrpc"
+        );
+        assert_eq!(
+            endpoint_url("https://127.0.0.1:7650/", DEFAULT_JSONRPC_PATH),
+            "https://127.0.0.1:7650/jsonrpc"
+        );
+    }
+
+    #[test]
+    fn parse_target_accepts_https() {
+        let url = parse_target("https://10.0.0.5:7650", false).unwrap();
+        assert_eq!(url.as_str(), "https://10.0.0.5:7650/");
+    }
+
+    #[test]
+    fn parse_target_accepts_loopback_http() {
+        assert!(parse_target("http://127.0.0.1:7650", false).is_ok());
+        assert!(parse_target("http://localhost:7650", false).is_ok());
+        assert!(parse_target("http://[::1]:7650", false).is_ok());
+    }
+
+    #[test]
+    fn parse_target_rejects_remote_http_unless_insecure() {
+        assert!(parse_target("http://10.0.0.5:7650", false).is_err());
+        assert!(parse_target("http://10.0.0.5:7650", true).is_ok());
+    }
+
+    #[test]
+    fn parse_target_rejects_bad_scheme_and_garbage() {
+        assert!(parse_target("ftp://127.0.0.1:7650", true).is_err());
+        assert!(parse_target("not a url", true).is_err());
+    }
+
+    #[test]
+    fn strict_tls_drops_loopback_trust_but_not_explicit_insecure() {
+        let local = reqwest::Url::parse("https://127.0.0.1:7650").unwrap();
+        let remote = reqwest::Url::parse("https://10.0.0.5:7650").unwrap();
+        assert!(allow_invalid_certs(&local, false, false));
+        assert!(!allow_invalid_certs(&local, false, true));
+        assert!(allow_invalid_certs(&local, true, true));
+        assert!(!allow_invalid_certs(&remote, false, false));
+    }
+
+    #[test]
+    fn load_ca_cert_rejects_missing_and_non_pem_files() {
+        let missing = load_ca_cert(Path::new("/nonexistent/ca.pem")).unwrap_err();
+        assert!(missing.contains("Cannot read I2PCONTROL_TLS_CA"));
+
+        let path = std::env::temp_dir().join(format!("i2pd-exporter-ca-{}", std::process::id()));
+        std::fs::write(&path, "not a certificate\n").unwrap();
+        let garbage = load_ca_cert(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(garbage.contains("contains no certificates"));
+    }
+
+    #[test]
+    fn display_address_strips_credentials_and_keeps_unix_paths() {
+        assert_eq!(
+            display_address("https://user:pw@10.0.0.5:7650/"),
+            "https://10.0.0.5:7650"
+        );
+        assert_eq!(
+            display_address("unix:/run/i2pd/i2pcontrol.sock"),
+            "unix:/run/i2pd/i2pcontrol.sock"
+        );
+    }
+
+    #[test]
+    fn jsonrpc_url_trims_trailing_slash() {
+        assert_eq!(
+            jsonrpc_url("https://127.0.0.1:7650/", DEFAULT_JSONRPC_PATH),
+            "https://127.0.0.1:7650/jsonrpc"
+        );
+    }
+
+    #[test]
+    fn jsonrpc_url_joins_paths_with_one_slash() {
+        for (base, path, want) in [
+            ("https://h:7650", "/jsonrpc", "https://h:7650/jsonrpc"),
+            ("https://h:7650//", "jsonrpc", "https://h:7650/jsonrpc"),
+            (
+                "https://h/i2p/",
+                "/api/jsonrpc",
+                "https://h/i2p/api/jsonrpc",
+            ),
+            ("https://h/i2p", "//rpc/", "https://h/i2p/rpc/"),
+            ("https://h:7650", "/", "https://h:7650/"),
+        ] {
+            assert_eq!(jsonrpc_url(base, path), want, "{} + {}", base, path);
+        }
+        assert_eq!(
+            endpoint_url("unix:/run/i2pd/i2pcontrol.sock", "/rpc"),
+            "http://localhost/rpc"
+        );
+    }
+}
@@ -1,74 +1,166 @@
 // I2PControl API type definitions
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
 
 // Result structure for the 'RouterInfo' method, containing various metrics
-#[derive(Debug, Deserialize, Default)]
+// Serialized with the snake_case field names for ?format=json.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct RouterInfoResult {
-    #[serde(rename = "i2p.router.status")]
+    #[serde(rename(deserialize = "i2p.router.status"))]
     #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub router_status: Option<u8>,
-    #[serde(rename = "i2p.router.version")]
+    #[serde(rename(deserialize = "i2p.router.version"))]
     pub router_version: Option<String>,
-    #[serde(rename = "i2p.router.uptime")]
+    #[serde(rename(deserialize = "i2p.router.uptime"))]
     #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub router_uptime: Option<u64>,
-    #[serde(rename = "i2p.router.net.bw.inbound.1s")]
+    #[serde(rename(deserialize = "i2p.router.net.bw.inbound.1s"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub bw_inbound_1s: Option<f64>,
-    #[serde(rename = "i2p.router.net.bw.inbound.15s")]
+    #[serde(rename(deserialize = "i2p.router.net.bw.inbound.15s"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub bw_inbound_15s: Option<f64>,
-    #[serde(rename = "i2p.router.net.bw.outbound.1s")]
+    #[serde(rename(deserialize = "i2p.router.net.bw.outbound.1s"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub bw_outbound_1s: Option<f64>,
-    #[serde(rename = "i2p.router.net.bw.outbound.15s")]
+    #[serde(rename(deserialize = "i2p.router.net.bw.outbound.15s"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub bw_outbound_15s: Option<f64>,
-    #[serde(rename = "i2p.router.net.bw.transit.15s")]
+    #[serde(rename(deserialize = "i2p.router.net.bw.transit.1s"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bw_transit_1s: Option<f64>,
+    #[serde(rename(deserialize = "i2p.router.net.bw.transit.15s"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub bw_transit_15s: Option<f64>,
-    #[serde(rename = "i2p.router.net.status")]
+    #[serde(rename(deserialize = "i2p.router.net.status"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_status: Option<u8>,
-    #[serde(rename = "i2p.router.net.status.v6")]
+    #[serde(rename(deserialize = "i2p.router.net.status.v6"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_status_v6: Option<u8>,
-    #[serde(rename = "i2p.router.net.error")]
+    #[serde(rename(deserialize = "i2p.router.net.error"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_error: Option<u8>,
-    #[serde(rename = "i2p.router.net.error.v6")]
+    #[serde(rename(deserialize = "i2p.router.net.error.v6"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_error_v6: Option<u8>,
-    #[serde(rename = "i2p.router.net.testing")]
+    #[serde(rename(deserialize = "i2p.router.net.testing"))]
     #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_testing: Option<u8>,
-    #[serde(rename = "i2p.router.net.testing.v6")]
+    #[serde(rename(deserialize = "i2p.router.net.testing.v6"))]
     #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_testing_v6: Option<u8>,
-    #[serde(rename = "i2p.router.net.tunnels.participating")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.participating"))]
     pub tunnels_participating: Option<u64>,
-    #[serde(rename = "i2p.router.net.tunnels.inbound")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.inbound"))]
     pub tunnels_inbound: Option<u64>,
-    #[serde(rename = "i2p.router.net.tunnels.outbound")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.outbound"))]
     pub tunnels_outbound: Option<u64>,
-    #[serde(rename = "i2p.router.net.tunnels.successrate")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.successrate"))]
     pub tunnels_successrate: Option<f64>,
-    #[serde(rename = "i2p.router.net.tunnels.totalsuccessrate")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.totalsuccessrate"))]
     pub tunnels_total_successrate: Option<f64>,
-    #[serde(rename = "i2p.router.net.tunnels.queue")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.queue"))]
     pub tunnels_queue: Option<u64>,
-    #[serde(rename = "i2p.router.net.tunnels.tbmqueue")]
+    #[serde(rename(deserialize = "i2p.router.net.tunnels.tbmqueue"))]
     pub tunnels_tbmqueue: Option<u64>,
-    #[serde(rename = "i2p.router.netdb.activepeers")]
+    #[serde(rename(deserialize = "i2p.router.netdb.activepeers"))]
     pub netdb_activepeers: Option<u64>,
-    #[serde(rename = "i2p.router.netdb.knownpeers")]
+    #[serde(rename(deserialize = "i2p.router.netdb.knownpeers"))]
     pub netdb_knownpeers: Option<u64>,
-    #[serde(rename = "i2p.router.netdb.floodfills")]
+    #[serde(rename(deserialize = "i2p.router.netdb.floodfills"))]
     pub netdb_floodfills: Option<u64>,
-    #[serde(rename = "i2p.router.netdb.leasesets")]
+    #[serde(rename(deserialize = "i2p.router.netdb.leasesets"))]
     pub netdb_leasesets: Option<u64>,
-    #[serde(rename = "i2p.router.net.total.received.bytes")]
+    #[serde(rename(deserialize = "i2p.router.net.total.received.bytes"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_total_received_bytes: Option<f64>,
-    #[serde(rename = "i2p.router.net.total.sent.bytes")]
+    #[serde(rename(deserialize = "i2p.router.net.total.sent.bytes"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_total_sent_bytes: Option<f64>,
-    #[serde(rename = "i2p.router.net.total.transit.bytes")]
+    #[serde(rename(deserialize = "i2p.router.net.total.transit.bytes"))]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
     pub net_total_transit_bytes: Option<f64>,
+    // Filled from NetworkSetting, not RouterInfo.
+    #[serde(skip_deserializing)]
+    pub net_bw_limit_in_kbps: Option<u64>,
+    #[serde(skip_deserializing)]
+    pub net_bw_limit_out_kbps: Option<u64>,
+    #[serde(skip_deserializing)]
+    pub net_bw_share_percent: Option<f64>,
+    // Local clock minus the HTTP Date header of the RouterInfo reply.
+    #[serde(skip_deserializing)]
+    pub clock_offset_seconds: Option<f64>,
+}
+
+// Result structure for the 'NetworkSetting' method (configured bandwidth limits, KBps)
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct NetworkSettingResult {
+    #[serde(rename = "i2p.router.net.bw.in")]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bw_in_kbps: Option<u64>,
+    #[serde(rename = "i2p.router.net.bw.out")]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bw_out_kbps: Option<u64>,
+    #[serde(rename = "i2p.router.net.bw.share")]
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub bw_share_percent: Option<f64>,
+}
+
+impl From<NetworkSettingResult> for RouterInfoResult {
+    fn from(ns: NetworkSettingResult) -> Self {
+        RouterInfoResult {
+            net_bw_limit_in_kbps: ns.bw_in_kbps,
+            net_bw_limit_out_kbps: ns.bw_out_kbps,
+            net_bw_share_percent: ns.bw_share_percent,
+            ..RouterInfoResult::default()
+        }
+    }
 }
 
 impl RouterInfoResult {
+    // Number of RouterInfo/NetworkSetting fields the router actually populated.
+    pub fn fields_present(&self) -> usize {
+        [
+            self.router_status.is_some(),
+            self.router_version.is_some(),
+            self.router_uptime.is_some(),
+            self.bw_inbound_1s.is_some(),
+            self.bw_inbound_15s.is_some(),
+            self.bw_outbound_1s.is_some(),
+            self.bw_outbound_15s.is_some(),
+            self.bw_transit_1s.is_some(),
+            self.bw_transit_15s.is_some(),
+            self.net_status.is_some(),
+            self.net_status_v6.is_some(),
+            self.net_error.is_some(),
+            self.net_error_v6.is_some(),
+            self.net_testing.is_some(),
+            self.net_testing_v6.is_some(),
+            self.tunnels_participating.is_some(),
+            self.tunnels_inbound.is_some(),
+            self.tunnels_outbound.is_some(),
+            self.tunnels_successrate.is_some(),
+            self.tunnels_total_successrate.is_some(),
+            self.tunnels_queue.is_some(),
+            self.tunnels_tbmqueue.is_some(),
+            self.netdb_activepeers.is_some(),
+            self.netdb_knownpeers.is_some(),
+            self.netdb_floodfills.is_some(),
+            self.netdb_leasesets.is_some(),
+            self.net_total_received_bytes.is_some(),
+            self.net_total_sent_bytes.is_some(),
+            self.net_total_transit_bytes.is_some(),
+            self.net_bw_limit_in_kbps.is_some(),
+            self.net_bw_limit_out_kbps.is_some(),
+            self.net_bw_share_percent.is_some(),
+        ]
+        .into_iter()
+        .filter(|present| *present)
+        .count()
+    }
+
     // Merge data from another RouterInfoResult, preferring values from `other` when present.
     pub fn merge_from(&mut self, other: RouterInfoResult) {
         if let Some(v) = other.router_status {
@@ -92,6 +184,9 @@ impl RouterInfoResult {
         if let Some(v) = other.bw_outbound_15s {
             self.bw_outbound_15s = Some(v);
         }
+        if let Some(v) = other.bw_transit_1s {
+            self.bw_transit_1s = Some(v);
+        }
         if let Some(v) = other.bw_transit_15s {
             self.bw_transit_15s = Some(v);
         }
@@ -155,5 +250,17 @@ impl RouterInfoResult {
         if let Some(v) = other.net_total_transit_bytes {
             self.net_total_transit_bytes = Some(v);
         }
+        if let Some(v) = other.net_bw_limit_in_kbps {
+            self.net_bw_limit_in_kbps = Some(v);
+        }
+        if let Some(v) = other.net_bw_limit_out_kbps {
+            self.net_bw_limit_out_kbps = Some(v);
+        }
+        if let Some(v) = other.net_bw_share_percent {
+            self.net_bw_share_percent = Some(v);
+        }
+        if let Some(v) = other.clock_offset_seconds {
+            self.clock_offset_seconds = Some(v);
+        }
     }
 }
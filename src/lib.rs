@@ -1,7 +1,12 @@
 // Library facade to expose modules for integration tests
 
+pub mod basic_auth;
 pub mod config;
 pub mod i2pcontrol;
+pub mod listener;
+pub mod logging;
 pub mod metrics;
+pub mod remote_write;
 pub mod server;
+pub mod tls_server;
 pub mod version;
@@ -0,0 +1,98 @@
+// TCP listener construction for the metrics server
+
+use std::io;
+use std::net::SocketAddr;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+
+// Pending-connection queue length, matching std's default.
+const LISTEN_BACKLOG: i32 = 1024;
+
+// A bound listener plus whether it accepts both IPv4 and IPv6 clients.
+pub struct BoundListener {
+    pub listener: TcpListener,
+    pub dual_stack: bool,
+}
+
+// Bind `addr` for the metrics server. The IPv6 wildcard (`[::]`) is made dual-stack
+// explicitly, since some platforms default sockets to IPV6_V6ONLY. With `reuse_port`,
+// SO_REUSEPORT lets another process bind the same address while this one still runs.
+pub fn bind(addr: SocketAddr, reuse_port: bool) -> io::Result<BoundListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    let dual_stack = match addr {
+        SocketAddr::V6(v6) if v6.ip().is_unspecified() => socket.set_only_v6(false).is_ok(),
+        _ => false,
+    };
+    // Same as tokio's own bind: allow quick restarts while old connections sit in TIME_WAIT.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    socket.set_nonblocking(true)?;
+    let listener = TcpListener::from_std(socket.into())?;
+    Ok(BoundListener {
+        listener,
+        dual_stack,
+    })
+}
+
+#[cfg(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+)))]
+fn set_reuse_port(_socket: &Socket) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not available on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ipv4_address_is_not_dual_stack() {
+        let bound = bind("127.0.0.1:0".parse().unwrap(), false).unwrap();
+        assert!(!bound.dual_stack);
+        assert!(bound.listener.local_addr().unwrap().is_ipv4());
+    }
+
+    #[tokio::test]
+    async fn ipv6_wildcard_accepts_ipv4_clients() {
+        // Hosts without IPv6 cannot bind [::]; nothing to check there.
+        let Ok(bound) = bind("[::]:0".parse().unwrap(), false) else {
+            return;
+        };
+        assert!(bound.dual_stack);
+        let port = bound.listener.local_addr().unwrap().port();
+        let connect = tokio::net::TcpStream::connect(("127.0.0.1", port));
+        let (accepted, connected) = tokio::join!(bound.listener.accept(), connect);
+        assert!(accepted.is_ok());
+        assert!(connected.is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn reuse_port_lets_two_listeners_share_an_address() {
+        let first = bind("127.0.0.1:0".parse().unwrap(), true).unwrap();
+        let addr = first.listener.local_addr().unwrap();
+        let second = bind(addr, true).unwrap();
+        assert_eq!(second.listener.local_addr().unwrap(), addr);
+
+        // Without the flag on the newcomer the address stays exclusive.
+        assert!(bind(addr, false).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+// Logger setup: env_logger's text format by default, or one JSON object per line
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+// One log line for Loki & co.: timestamp, level, module and message as JSON fields.
+fn json_line(timestamp: &str, record: &log::Record) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": record.level().as_str(),
+        "module": record.module_path().unwrap_or_else(|| record.target()),
+        "message": record.args().to_string(),
+    })
+    .to_string()
+}
+
+// Install the global logger at `level`; an explicit RUST_LOG still takes precedence.
+pub fn init(format: LogFormat, level: log::LevelFilter) {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(level)
+        .parse_env(env_logger::Env::default());
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let timestamp = buf.timestamp_millis().to_string();
+            writeln!(buf, "{}", json_line(&timestamp, record))
+        });
+    }
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_line_has_expected_fields() {
+        let line = json_line(
+            "2026-01-02T03:04:05.678Z",
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("i2pd_exporter::server")
+                .module_path(Some("i2pd_exporter::server"))
+                .args(format_args!("Scrape timed out; effective budget {}s", 2))
+                .build(),
+        );
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["timestamp"], "2026-01-02T03:04:05.678Z");
+        assert_eq!(v["level"], "WARN");
+        assert_eq!(v["module"], "i2pd_exporter::server");
+        assert_eq!(v["message"], "Scrape timed out; effective budget 2s");
+        assert!(!line.contains('\n'));
+    }
+}
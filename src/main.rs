@@ -1,75 +1,419 @@
 use std::sync::Arc;
 
-use clap::Parser;
-use log::{info, warn};
+use clap::CommandFactory;
+use log::{debug, info, warn};
 
 // Module declarations
+mod basic_auth;
 mod config;
 mod i2pcontrol;
+mod listener;
+mod logging;
 mod metrics;
+mod remote_write;
 mod server;
+mod tls_server;
 pub mod version;
 
 // Import types we need
 use config::{Cli, Config};
-use i2pcontrol::I2pControlClient;
+use i2pcontrol::{client, targets, ClientOptions, I2pControlClient, TargetClients};
+use metrics::{encode_metrics_text, ExporterCounters, ScrapeStats};
 
 // Exporter version available as `version::VERSION`
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Parse CLI + env into Config (handles --version automatically)
-    let cli = Cli::parse();
-    let cfg = Config::try_from(cli)?;
+    let matches = Cli::command().get_matches();
+    // Answered before the configuration is validated, like clap's own --version.
+    if matches.get_flag("version_json") {
+        println!("{}", version::version_json());
+        return Ok(());
+    }
+    let cfg = Config::from_matches(&matches)?;
+
+    logging::init(cfg.log_format, cfg.log_level);
+    // Shows which env vars and file keys took effect; secrets are redacted by Config's Debug.
+    debug!("Effective configuration: {:?}", cfg);
 
-    env_logger::init();
+    for listen in &cfg.listen_addrs {
+        if let Some(host) = &listen.host {
+            info!("Resolved METRICS_LISTEN_ADDR {} to {}", host, listen.addr);
+        }
+    }
 
     // Configuration
+    let listen_list = cfg
+        .listen_addrs
+        .iter()
+        .map(|l| l.addr.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
     info!(
         "Starting I2PControl exporter on {} (target: {})",
-        cfg.listen_addr, cfg.i2p_addr
+        listen_list, cfg.i2p_addr
     );
 
+    for key in cfg
+        .router_info_keys
+        .iter()
+        .filter(|k| !client::is_known_router_info_key(k))
+    {
+        warn!(
+            "RouterInfo key '{}' is not modeled by this exporter; requesting it anyway",
+            key
+        );
+    }
+
+    let (default_client, http_settings) = build_default_client(&cfg, ExporterCounters::default())?;
+    let state = Arc::new(TargetClients::new(
+        Arc::new(default_client),
+        cfg.tls_insecure,
+        http_settings,
+    ));
+
+    if cfg.dry_run {
+        return dry_run(&state, &cfg).await;
+    }
+
+    if let Some(deadline) = cfg.wait_for_router {
+        wait_for_router(&state, &cfg, deadline).await?;
+    }
+
+    // Build routes via server module
+    let routes = server::routes(
+        state.clone(),
+        server::RouteOptions {
+            metrics_path: cfg.metrics_path.clone(),
+            metrics_auth: cfg.metrics_auth.clone(),
+            extra_labels: cfg.extra_labels.clone(),
+            timeout_policy: timeout_policy(&cfg),
+            debug_endpoints: cfg.debug_endpoints,
+            net_status_no_data: cfg.net_status_no_data,
+        },
+    );
+
+    if cfg.debug_endpoints {
+        warn!("Debug endpoints enabled; /debug/routerinfo exposes raw router data");
+    }
+
+    // Load TLS material before binding so misconfiguration fails fast.
+    let tls = match &cfg.metrics_tls {
+        Some((cert, key)) => Some(tls_server::load_tls_config(cert, key)?),
+        None => None,
+    };
+
+    // Registered before binding, so a SIGHUP sent once the port answers never kills the process.
+    #[cfg(unix)]
+    let hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .map_err(|e| warn!("Cannot listen for SIGHUP; config reload disabled: {}", e))
+        .ok();
+
+    // Bind every address before serving so a bad one aborts startup.
+    let mut bound = Vec::with_capacity(cfg.listen_addrs.len());
+    for listen in &cfg.listen_addrs {
+        let b = listener::bind(listen.addr, cfg.reuse_port)
+            .map_err(|e| format!("Cannot bind {}: {}", listen.addr, e))?;
+        bound.push((listen.addr, b));
+    }
+
+    let mut servers = tokio::task::JoinSet::new();
+    for (addr, bound) in bound {
+        let local_addr = bound.listener.local_addr().unwrap_or(addr);
+        let stack = if bound.dual_stack {
+            "dual-stack IPv4/IPv6"
+        } else if local_addr.is_ipv6() {
+            "IPv6 only"
+        } else {
+            "IPv4 only"
+        };
+        let routes = routes.clone();
+        match &tls {
+            Some(tls) => {
+                info!("Listening on https://{} ({})", local_addr, stack);
+                servers.spawn(tls_server::serve_tls(routes, bound.listener, tls.clone()));
+            }
+            None => {
+                info!("Listening on http://{} ({})", local_addr, stack);
+                servers.spawn(async move {
+                    warp::serve(routes).incoming(bound.listener).run().await;
+                });
+            }
+        }
+    }
+
+    if let Some(url) = &cfg.push_url {
+        // The URL may carry credentials, so it is not logged.
+        info!(
+            "Pushing to the remote-write endpoint every {}s",
+            cfg.push_interval.as_secs()
+        );
+        let push_http = reqwest::Client::builder()
+            .user_agent(
+                cfg.user_agent
+                    .clone()
+                    .unwrap_or_else(|| format!("i2pd-exporter/{}", version::VERSION)),
+            )
+            .build()
+            .map_err(|e| format!("Cannot build remote-write HTTP client: {}", e))?;
+        servers.spawn(remote_write::run(
+            state.clone(),
+            push_http,
+            remote_write::PushOptions {
+                url: url.clone(),
+                interval: cfg.push_interval,
+                extra_labels: cfg.extra_labels.clone(),
+                net_status_no_data: cfg.net_status_no_data,
+                jitter: cfg.scrape_jitter,
+            },
+        ));
+    }
+
+    #[cfg(unix)]
+    if let Some(hangups) = hangups {
+        tokio::spawn(reload_on_sighup(hangups, state.clone(), cfg.clone()));
+    }
+
+    // Servers run until the process exits; one stopping ends the exporter.
+    if let Some(Err(e)) = servers.join_next().await {
+        return Err(format!("Metrics server task failed: {}", e).into());
+    }
+
+    Ok(())
+}
+
+// I2PControl client for the configured target; `counters` carry over across reloads.
+fn build_default_client(
+    cfg: &Config,
+    counters: ExporterCounters,
+) -> Result<(I2pControlClient, targets::HttpSettings), Box<dyn std::error::Error + Send + Sync>> {
     // Build an HTTP client for the I2PControl API
-    // Allow invalid certs if env set or host is loopback.
+    // Allow invalid certs if env set or host is loopback (unless --strict-tls).
     let tls_insecure_env = cfg.tls_insecure;
-    let host_is_loopback = reqwest::Url::parse(&cfg.i2p_addr)
-        .ok()
-        .and_then(|u| u.host_str().map(|h| h.to_string()))
-        .map(|host| {
-            host.eq_ignore_ascii_case("localhost")
-                || host
-                    .parse::<std::net::IpAddr>()
-                    .map(|ip| ip.is_loopback())
-                    .unwrap_or(false)
-        })
-        .unwrap_or(false);
-    let allow_insecure = tls_insecure_env || host_is_loopback;
+    let unix_socket = targets::unix_socket_path(&cfg.i2p_addr);
+    let host_is_loopback = unix_socket.is_some()
+        || reqwest::Url::parse(&cfg.i2p_addr)
+            .map(|u| targets::is_loopback_target(&u))
+            .unwrap_or(false);
+    let allow_insecure = tls_insecure_env || (host_is_loopback && !cfg.strict_tls);
 
     if tls_insecure_env {
         warn!("I2PCONTROL_TLS_INSECURE=1 set; accepting invalid TLS certificates");
+    } else if cfg.strict_tls {
+        info!("Strict TLS enabled; verifying certificates for every target including loopback");
     } else if host_is_loopback {
         info!("Loopback target detected; allowing self-signed certificate");
     }
 
-    let api_client = reqwest::Client::builder()
-        .http1_only()
-        .danger_accept_invalid_certs(allow_insecure)
-        .user_agent(format!("i2pd-exporter/{}", version::VERSION))
-        .build()?;
+    let http_settings = targets::HttpSettings {
+        proxy: cfg.proxy.clone(),
+        unix_socket,
+        user_agent: cfg.user_agent.clone(),
+        strict_tls: cfg.strict_tls,
+        http2: cfg.http2,
+        connect_timeout: cfg.connect_timeout,
+        ca_cert: match &cfg.tls_ca_cert {
+            Some(path) => Some(targets::load_ca_cert(path)?),
+            None => None,
+        },
+    };
+    if let Some(path) = &cfg.tls_ca_cert {
+        info!("Trusting I2PControl CA certificate from {}", path.display());
+    }
+    // The proxy URL may carry credentials, so it is not logged.
+    if http_settings.proxy.is_some() {
+        info!("Routing I2PControl requests through the configured proxy");
+    }
+    let api_client = targets::build_http_client(
+        allow_insecure,
+        &http_settings,
+        &counters.connections_created,
+    )
+    .map_err(|e| format!("Cannot build I2PControl HTTP client: {}", e))?;
 
-    let state = Arc::new(I2pControlClient::new(
+    let mut default_client = I2pControlClient::new(
         api_client,
-        format!("{}/jsonrpc", cfg.i2p_addr.trim_end_matches('/')),
-        cfg.max_scrape_timeout,
-    ));
+        targets::endpoint_url(&cfg.i2p_addr, &cfg.jsonrpc_path),
+        ClientOptions {
+            max_scrape_timeout: cfg.max_scrape_timeout,
+            cache_ttl: cfg.cache_ttl,
+            max_concurrent_scrapes: cfg.max_concurrent_scrapes,
+            rpc_retries: cfg.rpc_retries,
+            rpc_call_timeout: cfg.rpc_call_timeout,
+            max_response_bytes: cfg.max_response_bytes,
+            batch_rpc: cfg.batch_rpc,
+            router_info_keys: cfg.router_info_keys.clone(),
+            jsonrpc_path: cfg.jsonrpc_path.clone(),
+        },
+        counters,
+    );
+    // Unix socket targets talk to a placeholder URL; label them with the configured path.
+    default_client.target_address = targets::display_address(&cfg.i2p_addr);
+    Ok((default_client, http_settings))
+}
 
-    // Build routes via server module
-    let routes = server::routes(state.clone());
+fn timeout_policy(cfg: &Config) -> server::ScrapeTimeoutPolicy {
+    server::ScrapeTimeoutPolicy {
+        margin: cfg.scrape_timeout_margin,
+        margin_threshold: cfg.scrape_timeout_margin_threshold,
+        min_usable: cfg.min_usable_scrape_timeout,
+        default_timeout: cfg.default_scrape_timeout,
+        absolute_max: Some(cfg.absolute_max_scrape_timeout),
+    }
+}
 
-    info!("Listening on http://{}", cfg.listen_addr);
-    // Start the Warp server (simple run; graceful shutdown not available in this resolved Warp)
-    warp::serve(routes).run(cfg.listen_addr).await;
+// Settings bound once at startup; a reload can only point out that they changed.
+fn restart_only_changes(old: &Config, new: &Config) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if old.listen_addrs != new.listen_addrs || old.reuse_port != new.reuse_port {
+        changed.push("listen address");
+    }
+    if old.metrics_path != new.metrics_path || old.metrics_tls != new.metrics_tls {
+        changed.push("metrics path or TLS");
+    }
+    if old.extra_labels != new.extra_labels || old.debug_endpoints != new.debug_endpoints {
+        changed.push("labels or debug endpoints");
+    }
+    if old.net_status_no_data != new.net_status_no_data {
+        changed.push("net status placeholder");
+    }
+    if old.log_format != new.log_format || old.log_level != new.log_level {
+        changed.push("logging");
+    }
+    if old.push_url != new.push_url
+        || old.push_interval != new.push_interval
+        || old.scrape_jitter != new.scrape_jitter
+    {
+        changed.push("remote-write push");
+    }
+    changed
+}
+
+// Re-read the config file and environment on SIGHUP and swap in a client for the new
+// target, TLS and scrape settings, plus the metrics auth and scrape timeout policy. A config that fails to load leaves the old one running.
+#[cfg(unix)]
+async fn reload_on_sighup(
+    mut hangups: tokio::signal::unix::Signal,
+    state: Arc<TargetClients>,
+    started: Config,
+) {
+    while hangups.recv().await.is_some() {
+        info!("SIGHUP received; reloading configuration");
+        let cfg = match Cli::command()
+            .try_get_matches()
+            .map_err(|e| e.to_string())
+            .and_then(|m| Config::from_matches(&m).map_err(|e| e.to_string()))
+        {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                warn!(
+                    "Config reload failed; keeping the running configuration: {}",
+                    e
+                );
+                continue;
+            }
+        };
+        for what in restart_only_changes(&started, &cfg) {
+            warn!("Reload ignores the changed {}; restart to apply it", what);
+        }
+        debug!("Reloaded configuration: {:?}", cfg);
+        match build_default_client(&cfg, state.default_client().counters()) {
+            Ok((client, http)) => {
+                state.replace(Arc::new(client), cfg.tls_insecure, http);
+                // Auth and the timeout policy apply to the next request; the rest stays as started.
+                state.replace_route_options(server::RouteOptions {
+                    metrics_auth: cfg.metrics_auth.clone(),
+                    timeout_policy: timeout_policy(&cfg),
+                    ..(*state.route_options()).clone()
+                });
+                info!("Configuration reloaded (target: {})", cfg.i2p_addr);
+            }
+            Err(e) => warn!(
+                "Config reload failed; keeping the running configuration: {}",
+                e
+            ),
+        }
+    }
+}
+
+// Pause between startup attempts while the router is still coming up.
+const WAIT_FOR_ROUTER_RETRY: std::time::Duration = std::time::Duration::from_secs(1);
+
+// Block until the default router answers RouterInfo, giving up after `deadline`.
+async fn wait_for_router(
+    state: &TargetClients,
+    cfg: &Config,
+    deadline: std::time::Duration,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = state.default_client();
+    let give_up = std::time::Instant::now() + deadline;
+    info!(
+        "Waiting up to {}s for the router to answer before serving",
+        deadline.as_secs()
+    );
+    let mut attempt = 0u32;
+    loop {
+        attempt += 1;
+        let remaining = give_up.saturating_duration_since(std::time::Instant::now());
+        let budget = remaining.min(cfg.max_scrape_timeout);
+        let err = match tokio::time::timeout(budget, client.fetch_router_info(budget)).await {
+            Ok(Ok(_)) => {
+                info!("Router answered after {} attempt(s)", attempt);
+                return Ok(());
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("timed out after {:.3}s", budget.as_secs_f64()),
+        };
+        let remaining = give_up.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(format!(
+                "Router did not answer within {}s (last error: {})",
+                deadline.as_secs(),
+                err
+            )
+            .into());
+        }
+        warn!("Router not ready (attempt {}): {}", attempt, err);
+        tokio::time::sleep(remaining.min(WAIT_FOR_ROUTER_RETRY)).await;
+    }
+}
 
+// One scrape of the default router within MAX_SCRAPE_TIMEOUT_SECONDS, printed to stdout.
+async fn dry_run(
+    state: &TargetClients,
+    cfg: &Config,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let client = state.default_client();
+    let t0 = std::time::Instant::now();
+    let data = tokio::time::timeout(
+        cfg.max_scrape_timeout,
+        client.fetch_router_info(cfg.max_scrape_timeout),
+    )
+    .await
+    .map_err(|_| {
+        format!(
+            "Dry run timed out after {:.3}s",
+            cfg.max_scrape_timeout.as_secs_f64()
+        )
+    })?
+    .map_err(|e| format!("Dry run failed: {}", e))?;
+    client.observe_scrape_duration(t0.elapsed().as_secs_f64());
+
+    let stats = ScrapeStats {
+        effective_timeout_seconds: Some(cfg.max_scrape_timeout.as_secs_f64()),
+        max_scrape_timeout_seconds: Some(cfg.max_scrape_timeout.as_secs_f64()),
+        router_up: true,
+        rpc_response_bytes: client.last_response_bytes(),
+        routerinfo_duration_seconds: client.last_routerinfo_seconds(),
+        target_address: Some(client.target_address.clone()),
+        seconds_since_last_success: client.seconds_since_last_success(),
+        counters: client.counters(),
+        ..ScrapeStats::default()
+    };
+    print!(
+        "{}",
+        encode_metrics_text(Some(&data), &stats, &version::BUILD_INFO, &cfg.extra_labels)
+    );
     Ok(())
 }
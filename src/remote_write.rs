@@ -0,0 +1,265 @@
+// Prometheus remote-write push mode: scrape on a timer and POST the samples (protobuf + snappy)
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use tokio::time::MissedTickBehavior;
+
+use crate::i2pcontrol::{I2pControlClient, TargetClients};
+use crate::metrics::{encode_metrics_text, series_from_text, Series};
+use crate::server::{collect_scrape, MetricsScope};
+use crate::version;
+
+#[derive(Debug, Clone)]
+pub struct PushOptions {
+    pub url: String,
+    pub interval: Duration,
+    pub extra_labels: Vec<(String, String)>,
+    pub net_status_no_data: bool,
+    pub jitter: Duration,
+}
+
+// Random delays in `[0, max]` from a splitmix64 sequence; a fixed seed replays the same delays.
+pub struct Jitter {
+    max: Duration,
+    state: u64,
+}
+
+impl Jitter {
+    pub fn new(max: Duration, seed: u64) -> Self {
+        Jitter { max, state: seed }
+    }
+
+    // Seeded from the clock and pid, so exporters started together still spread out.
+    pub fn from_entropy(max: Duration) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Jitter::new(max, nanos ^ ((std::process::id() as u64) << 32))
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        if self.max.is_zero() {
+            return Duration::ZERO;
+        }
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        // Top 53 bits as a fraction in [0, 1).
+        self.max.mul_f64((z >> 11) as f64 / (1u64 << 53) as f64)
+    }
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+// Length-delimited protobuf field (wire type 2).
+fn put_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(buf, (field << 3) | 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+// prometheus.WriteRequest: TimeSeries{labels = 1, samples = 2}, Sample{value = 1, timestamp = 2}.
+pub fn encode_write_request(series: &[Series], timestamp_ms: i64) -> Vec<u8> {
+    let mut req = Vec::new();
+    let mut ts = Vec::new();
+    let mut item = Vec::new();
+    for s in series {
+        ts.clear();
+        for (name, value) in &s.labels {
+            item.clear();
+            put_bytes(&mut item, 1, name.as_bytes());
+            put_bytes(&mut item, 2, value.as_bytes());
+            put_bytes(&mut ts, 1, &item);
+        }
+        item.clear();
+        item.push(0x09); // field 1, 64-bit
+        item.extend_from_slice(&s.value.to_le_bytes());
+        item.push(0x10); // field 2, varint
+        put_varint(&mut item, timestamp_ms as u64);
+        put_bytes(&mut ts, 2, &item);
+        put_bytes(&mut req, 1, &ts);
+    }
+    req
+}
+
+// Scrape once and push the result; returns the number of samples sent.
+pub async fn push_once(
+    client: &I2pControlClient,
+    http: &reqwest::Client,
+    options: &PushOptions,
+) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    // Each push has to finish before the next one is due.
+    let budget = options.interval.min(client.options.max_scrape_timeout);
+    let t0 = Instant::now();
+    let (_, data, mut stats) = collect_scrape(client, budget, MetricsScope::Full).await;
+    stats.net_status_no_data = options.net_status_no_data;
+    client.observe_scrape_duration(t0.elapsed().as_secs_f64());
+    let text = encode_metrics_text(
+        data.as_ref(),
+        &stats,
+        &version::BUILD_INFO,
+        &options.extra_labels,
+    );
+
+    let series = series_from_text(&text);
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0);
+    let body =
+        snap::raw::Encoder::new().compress_vec(&encode_write_request(&series, timestamp_ms))?;
+
+    let resp = http
+        .post(&options.url)
+        .header(CONTENT_TYPE, "application/x-protobuf")
+        .header(CONTENT_ENCODING, "snappy")
+        .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+        .body(body)
+        .timeout(options.interval)
+        .send()
+        .await?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body: String = resp
+            .text()
+            .await
+            .unwrap_or_default()
+            .chars()
+            .take(256)
+            .collect();
+        return Err(format!("remote-write endpoint answered HTTP {}: {}", status, body).into());
+    }
+    Ok(series.len())
+}
+
+// Push every interval until the process exits; failures are logged and retried next tick.
+// Each push uses the current default client, so a config reload applies from the next tick.
+pub async fn run(
+    targets: std::sync::Arc<TargetClients>,
+    http: reqwest::Client,
+    options: PushOptions,
+) {
+    let mut ticker = tokio::time::interval(options.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut jitter = Jitter::from_entropy(options.jitter);
+    loop {
+        ticker.tick().await;
+        // Pushes stay one interval apart on average; only their offset within it varies.
+        tokio::time::sleep(jitter.next_delay()).await;
+        match push_once(&targets.default_client(), &http, &options).await {
+            Ok(n) => debug!("Pushed {} samples to the remote-write endpoint", n),
+            Err(e) => warn!("Remote-write push failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2pcontrol::ClientOptions;
+    use crate::metrics::ExporterCounters;
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    fn labels(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn encodes_write_request_protobuf() {
+        let series = [Series {
+            labels: labels(&[("__name__", "up")]),
+            value: 1.0,
+        }];
+        let mut expected = vec![0x0a, 0x1e, 0x0a, 0x0e, 0x0a, 0x08];
+        expected.extend_from_slice(b"__name__");
+        expected.extend_from_slice(&[0x12, 0x02]);
+        expected.extend_from_slice(b"up");
+        expected.extend_from_slice(&[0x12, 0x0c, 0x09]);
+        expected.extend_from_slice(&1.0f64.to_le_bytes());
+        expected.extend_from_slice(&[0x10, 0xac, 0x02]);
+        assert_eq!(encode_write_request(&series, 300), expected);
+    }
+
+    #[test]
+    fn jitter_is_bounded_and_reproducible_from_a_seed() {
+        let max = Duration::from_secs(2);
+        let delays = |seed| {
+            let mut jitter = Jitter::new(max, seed);
+            (0..100).map(|_| jitter.next_delay()).collect::<Vec<_>>()
+        };
+        let first = delays(42);
+        assert_eq!(first, delays(42));
+        assert_ne!(first, delays(43));
+        assert!(first.iter().all(|d| *d <= max));
+        // Spread over the whole range rather than clustered at one end.
+        assert!(first.iter().any(|d| *d < max / 4) && first.iter().any(|d| *d > max * 3 / 4));
+
+        let mut none = Jitter::new(Duration::ZERO, 42);
+        assert_eq!(none.next_delay(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn pushes_snappy_protobuf_with_remote_write_headers() {
+        type Pushes = Vec<(warp::http::HeaderMap, Vec<u8>)>;
+        let received: Arc<Mutex<Pushes>> = Arc::default();
+        let sink = received.clone();
+        let receiver = warp::post()
+            .and(warp::header::headers_cloned())
+            .and(warp::body::bytes())
+            .map(move |headers, body: warp::hyper::body::Bytes| {
+                sink.lock().unwrap().push((headers, body.to_vec()));
+                warp::reply()
+            });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(warp::serve(receiver).incoming(listener).run());
+
+        // The router is unreachable, so only self-metrics are pushed.
+        let client = I2pControlClient::new(
+            reqwest::Client::new(),
+            "http://127.0.0.1:1/".to_string(),
+            ClientOptions {
+                max_scrape_timeout: Duration::from_secs(2),
+                rpc_retries: 0,
+                ..ClientOptions::default()
+            },
+            ExporterCounters::default(),
+        );
+        let options = PushOptions {
+            url: format!("http://{}/api/v1/write", addr),
+            interval: Duration::from_secs(5),
+            extra_labels: vec![],
+            net_status_no_data: false,
+            jitter: Duration::ZERO,
+        };
+        let sent = push_once(&client, &reqwest::Client::new(), &options)
+            .await
+            .unwrap();
+        assert!(sent > 0);
+
+        let received = received.lock().unwrap();
+        let (headers, body) = &received[0];
+        assert_eq!(headers["content-encoding"], "snappy");
+        assert_eq!(headers["content-type"], "application/x-protobuf");
+        assert_eq!(headers["x-prometheus-remote-write-version"], "0.1.0");
+        let proto = snap::raw::Decoder::new().decompress_vec(body).unwrap();
+        let contains = |needle: &[u8]| proto.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"i2pd_exporter_last_scrape_error"));
+        assert!(contains(b"i2p_router_up"));
+    }
+}
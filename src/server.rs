@@ -1,32 +1,84 @@
 // HTTP server handlers
 
+use std::io::Write;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use log::{error, warn};
+use serde::Deserialize;
 use warp::http::HeaderMap;
 use warp::{self, Filter, Reply};
 
-use crate::i2pcontrol::rpc::RpcCallError;
-use crate::i2pcontrol::I2pControlClient;
-use crate::metrics::encode_metrics_text;
+use crate::basic_auth::BasicCredentials;
+use crate::i2pcontrol::client::lite_router_info_keys;
+use crate::i2pcontrol::rpc::{redact_sensitive_fields, ErrorKind, RpcCallError};
+use crate::i2pcontrol::types::RouterInfoResult;
+use crate::i2pcontrol::{I2pControlClient, TargetClients};
+use crate::metrics::{
+    encode_metrics_json, encode_metrics_text, to_influx_line_protocol, to_prometheus_text,
+    ScrapeStats,
+};
 use crate::version;
 
-// Compute effective timeout strictly from the Prometheus header.
-// Returns None if the header is missing or invalid. Applies a 0.5s margin only when header > 3s,
-// and clamps the final value to at least 0.1s.
-fn effective_timeout(headers: &HeaderMap, hard_max: Duration) -> Option<Duration> {
-    const MARGIN: f64 = 0.5;
-    const MARGIN_THRESHOLD: f64 = 3.0; // apply margin only when header > 3s
+// How the Prometheus timeout header is turned into a scrape budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrapeTimeoutPolicy {
+    // Subtracted from the header so the response beats Prometheus' own deadline.
+    pub margin: Duration,
+    // The margin only applies when the header exceeds this.
+    pub margin_threshold: Duration,
+    // Budgets below this are flagged: they rarely fit even a loopback TLS handshake.
+    pub min_usable: Duration,
+    // Budget for scrapes without the header (e.g. curl); None rejects them with 400.
+    pub default_timeout: Option<Duration>,
+    // Highest cap a request may ask for with `?max_timeout=`; None allows lowering only.
+    pub absolute_max: Option<Duration>,
+}
+
+impl Default for ScrapeTimeoutPolicy {
+    fn default() -> Self {
+        ScrapeTimeoutPolicy {
+            margin: Duration::from_millis(500),
+            margin_threshold: Duration::from_secs(3),
+            min_usable: Duration::from_secs(1),
+            default_timeout: None,
+            absolute_max: None,
+        }
+    }
+}
 
-    let secs = headers
-        .get("X-Prometheus-Scrape-Timeout-Seconds")
-        .and_then(|v| v.to_str().ok())
+const TIMEOUT_HEADER: &str = "X-Prometheus-Scrape-Timeout-Seconds";
+
+// Raw Prometheus scrape timeout from the header, when present and numeric.
+fn requested_timeout(headers: &HeaderMap) -> Option<f64> {
+    headers
+        .get(TIMEOUT_HEADER)?
+        .to_str()
+        .ok()
         .and_then(|s| s.parse::<f64>().ok())
-        .filter(|v| v.is_finite())?;
+        .filter(|v| v.is_finite())
+}
+
+// Compute effective timeout from the Prometheus header.
+// A missing header falls back to the policy default, if any; otherwise (or for an invalid
+// header) returns None. Applies the policy margin only when the header exceeds its threshold,
+// and clamps the final value to at least 0.1s.
+fn effective_timeout(
+    headers: &HeaderMap,
+    hard_max: Duration,
+    policy: &ScrapeTimeoutPolicy,
+) -> Option<Duration> {
+    if !headers.contains_key(TIMEOUT_HEADER) {
+        // No Prometheus deadline to beat, so the default is used without a margin.
+        let default = policy.default_timeout?.max(Duration::from_millis(100));
+        return Some(default.min(hard_max));
+    }
+    let secs = requested_timeout(headers)?;
 
-    let adjusted = if secs > MARGIN_THRESHOLD {
-        secs - MARGIN
+    let adjusted = if secs > policy.margin_threshold.as_secs_f64() {
+        secs - policy.margin.as_secs_f64()
     } else {
         secs
     };
@@ -35,186 +87,1640 @@ fn effective_timeout(headers: &HeaderMap, hard_max: Duration) -> Option<Duration
     Some(Duration::from_secs_f64(capped))
 }
 
-// Very small Accept negotiation: prefer OpenMetrics when the client
-// either accepts it explicitly or does not specify a preference.
-// We always emit OpenMetrics text, so the content type must match.
+// Per-request hard cap from `?max_timeout=<seconds>`, bounded by the policy ceiling.
+fn request_hard_max(
+    max_timeout: Option<&str>,
+    hard_max: Duration,
+    policy: &ScrapeTimeoutPolicy,
+) -> Result<Duration, String> {
+    let Some(raw) = max_timeout else {
+        return Ok(hard_max);
+    };
+    let secs = raw
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .ok_or_else(|| format!("Invalid max_timeout '{}': expected positive seconds", raw))?;
+    let ceiling = policy.absolute_max.unwrap_or(hard_max).max(hard_max);
+    if secs > ceiling.as_secs_f64() {
+        return Err(format!(
+            "max_timeout {} exceeds the allowed maximum of {}s",
+            raw,
+            ceiling.as_secs_f64()
+        ));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+// Content negotiation: OpenMetrics unless the client prefers the classic Prometheus text
+// format, honouring q-values; 406 only when the client refuses both formats outright.
 const OM_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
-fn choose_content_type(headers: &HeaderMap) -> &'static str {
-    match headers.get("Accept").and_then(|v| v.to_str().ok()) {
-        Some(accept) => {
-            let a = accept.to_ascii_lowercase();
-            if a.contains("application/openmetrics-text") || a.contains("*/*") {
-                OM_CONTENT_TYPE
-            } else {
-                // We only encode OpenMetrics; be precise about what we return.
-                OM_CONTENT_TYPE
+const PROM_TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const INFLUX_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExpositionFormat {
+    OpenMetrics,
+    PrometheusText,
+    Influx, // Only via `?format=influx`; never chosen from Accept
+    Json,   // Only via `?format=json`; never chosen from Accept
+}
+
+impl ExpositionFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExpositionFormat::OpenMetrics => OM_CONTENT_TYPE,
+            ExpositionFormat::PrometheusText => PROM_TEXT_CONTENT_TYPE,
+            ExpositionFormat::Influx => INFLUX_CONTENT_TYPE,
+            ExpositionFormat::Json => JSON_CONTENT_TYPE,
+        }
+    }
+
+    fn media_type(self) -> (&'static str, &'static str) {
+        match self {
+            ExpositionFormat::OpenMetrics => ("application", "openmetrics-text"),
+            ExpositionFormat::PrometheusText | ExpositionFormat::Influx => ("text", "plain"),
+            ExpositionFormat::Json => ("application", "json"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentCoding {
+    Identity,
+    Gzip,
+}
+
+// Split an Accept-style header into lowercase (token, q) pairs; a missing or bad q counts as 1.
+fn parse_q_list(value: &str) -> Vec<(String, f64)> {
+    value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let token = parts.next().filter(|t| !t.is_empty())?.to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+                .find_map(|q| q.trim().parse::<f64>().ok())
+                .filter(|q| q.is_finite())
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0);
+            Some((token, q))
+        })
+        .collect()
+}
+
+// q for a format from its most specific matching range (type/subtype > type/* > */*).
+fn format_q(ranges: &[(String, f64)], format: ExpositionFormat) -> Option<f64> {
+    let (ty, sub) = format.media_type();
+    let exact = format!("{}/{}", ty, sub);
+    let wildcard_sub = format!("{}/*", ty);
+    [exact.as_str(), wildcard_sub.as_str(), "*/*"]
+        .iter()
+        .find_map(|want| ranges.iter().find(|(r, _)| r == want).map(|(_, q)| *q))
+}
+
+// None means every format we can produce was refused with q=0.
+fn choose_format(headers: &HeaderMap) -> Option<ExpositionFormat> {
+    let Some(accept) = headers.get("Accept").and_then(|v| v.to_str().ok()) else {
+        return Some(ExpositionFormat::OpenMetrics);
+    };
+    let ranges = parse_q_list(accept);
+    // Unlisted formats stay acceptable as a last resort, so odd Accept values still get metrics.
+    let score = |format| match format_q(&ranges, format) {
+        Some(q) if q <= 0.0 => None,
+        Some(q) => Some(q),
+        None => Some(0.0),
+    };
+    match (
+        score(ExpositionFormat::OpenMetrics),
+        score(ExpositionFormat::PrometheusText),
+    ) {
+        (Some(om), Some(text)) if text > om => Some(ExpositionFormat::PrometheusText),
+        (Some(_), _) => Some(ExpositionFormat::OpenMetrics),
+        (None, Some(_)) => Some(ExpositionFormat::PrometheusText),
+        (None, None) => None,
+    }
+}
+
+// gzip whenever acceptable; otherwise identity unless it was refused (`identity;q=0` or `*;q=0`).
+fn choose_encoding(headers: &HeaderMap) -> Option<ContentCoding> {
+    let Some(value) = headers.get("Accept-Encoding").and_then(|v| v.to_str().ok()) else {
+        return Some(ContentCoding::Identity);
+    };
+    let codings = parse_q_list(value);
+    let q_of = |name: &str| {
+        codings
+            .iter()
+            .find(|(c, _)| c == name)
+            .or_else(|| codings.iter().find(|(c, _)| c == "*"))
+            .map(|(_, q)| *q)
+    };
+    if q_of("gzip").is_some_and(|q| q > 0.0) {
+        Some(ContentCoding::Gzip)
+    } else if q_of("identity").is_none_or(|q| q > 0.0) {
+        Some(ContentCoding::Identity)
+    } else {
+        None
+    }
+}
+
+// Format and coding for a metrics response, or None for 406 Not Acceptable.
+fn negotiate(headers: &HeaderMap) -> Option<(ExpositionFormat, ContentCoding)> {
+    Some((choose_format(headers)?, choose_encoding(headers)?))
+}
+
+fn not_acceptable() -> warp::reply::Response {
+    let reply = warp::reply::with_status(
+        "Not Acceptable: supported formats are application/openmetrics-text and text/plain \
+         (identity or gzip)",
+        warp::http::StatusCode::NOT_ACCEPTABLE,
+    );
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    let reply = warp::reply::with_header(reply, "Vary", "Accept, Accept-Encoding");
+    reply.into_response()
+}
+
+fn gzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+    encoder.finish()
+}
+
+// Which key set a metrics route requests from the router
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsScope {
+    Full, // /metrics: every configured key plus NetworkSetting, cache-aware
+    Lite, // /metrics-lite: status and bandwidth only, always fresh
+}
+
+// Query parameters accepted by /metrics
+#[derive(Debug, Default, Deserialize)]
+pub struct MetricsQuery {
+    pub target: Option<String>,
+    pub format: Option<String>, // `influx` or `json`; absent uses Accept
+    pub max_timeout: Option<String>, // Seconds; replaces the hard cap for this request
+}
+
+// Why no response format could be chosen for a metrics request.
+enum FormatRejection {
+    Unsupported(String), // Unknown ?format= value -> 400
+    NotAcceptable,       // Accept/Accept-Encoding refuse everything -> 406
+}
+
+impl FormatRejection {
+    fn into_response(self) -> warp::reply::Response {
+        match self {
+            FormatRejection::Unsupported(format) => {
+                warn!("Rejecting scrape: unsupported format '{}'", format);
+                let reply = warp::reply::with_status(
+                    format!("Unsupported format '{}' (supported: influx, json)", format),
+                    warp::http::StatusCode::BAD_REQUEST,
+                );
+                let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+                reply.into_response()
+            }
+            FormatRejection::NotAcceptable => {
+                warn!("Rejecting scrape: Accept/Accept-Encoding refuse every supported format");
+                not_acceptable()
             }
         }
-        None => OM_CONTENT_TYPE,
     }
 }
 
+// An explicit ?format= wins over Accept; only the content coding is still negotiated.
+fn response_format(
+    format: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<(ExpositionFormat, ContentCoding), FormatRejection> {
+    let negotiated = match format {
+        None => negotiate(headers),
+        Some("influx") => choose_encoding(headers).map(|c| (ExpositionFormat::Influx, c)),
+        Some("json") => choose_encoding(headers).map(|c| (ExpositionFormat::Json, c)),
+        Some(other) => return Err(FormatRejection::Unsupported(other.to_string())),
+    };
+    negotiated.ok_or(FormatRejection::NotAcceptable)
+}
+
 // Define a small async handler function for /metrics
 pub async fn metrics_handler(
-    st: Arc<I2pControlClient>,
+    targets: Arc<TargetClients>,
+    options: Arc<RouteOptions>,
+    scope: MetricsScope,
+    query: MetricsQuery,
     headers: HeaderMap,
-) -> Result<impl warp::Reply, warp::Rejection> {
+) -> Result<warp::reply::Response, warp::Rejection> {
     let t0 = Instant::now();
 
-    // Require the Prometheus timeout header and compute the effective timeout
-    let Some(effective_timeout) = effective_timeout(&headers, st.max_scrape_timeout) else {
-        let msg = "missing or invalid X-Prometheus-Scrape-Timeout-Seconds header".to_string();
-        let reply = warp::reply::with_status(msg, warp::http::StatusCode::BAD_REQUEST);
-        let reply = warp::reply::with_header(reply, "Content-Type", choose_content_type(&headers));
-        let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
-        return Ok(reply);
+    // Refuse before touching the router when no response format would be accepted.
+    let negotiated = match response_format(query.format.as_deref(), &headers) {
+        Ok(negotiated) => negotiated,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let st = match targets.resolve(query.target.as_deref()) {
+        Ok(client) => client,
+        Err(err) => {
+            warn!("Rejecting scrape: {}", err);
+            let reply =
+                warp::reply::with_status(err.to_string(), warp::http::StatusCode::BAD_REQUEST);
+            let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+            return Ok(reply.into_response());
+        }
+    };
+
+    let hard_max = match request_hard_max(
+        query.max_timeout.as_deref(),
+        st.options.max_scrape_timeout,
+        &options.timeout_policy,
+    ) {
+        Ok(hard_max) => hard_max,
+        Err(msg) => {
+            warn!("Rejecting scrape: {}", msg);
+            let reply = warp::reply::with_status(msg, warp::http::StatusCode::BAD_REQUEST);
+            let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+            return Ok(reply.into_response());
+        }
+    };
+
+    // Require the Prometheus timeout header and compute the effective timeout.
+    // Without it, still render self-metrics so the misconfiguration shows up in Prometheus.
+    let Some(effective_timeout) = effective_timeout(&headers, hard_max, &options.timeout_policy)
+    else {
+        warn!("Rejecting scrape: missing or invalid X-Prometheus-Scrape-Timeout-Seconds header");
+        st.record_missing_timeout_header();
+        st.record_scrape_result(false);
+        st.observe_scrape_duration(t0.elapsed().as_secs_f64());
+        let stats = ScrapeStats {
+            counters: st.counters(),
+            last_scrape_error: 1,
+            error_message: Some(
+                "missing or invalid X-Prometheus-Scrape-Timeout-Seconds header".to_string(),
+            ),
+            rpc_response_bytes: st.last_response_bytes(),
+            routerinfo_duration_seconds: st.last_routerinfo_seconds(),
+            seconds_since_last_success: st.seconds_since_last_success(),
+            net_status_no_data: options.net_status_no_data,
+            target_address: Some(st.target_address.clone()),
+            max_scrape_timeout_seconds: Some(st.options.max_scrape_timeout.as_secs_f64()),
+            ..ScrapeStats::default()
+        };
+        let body = encode_body(negotiated.0, None, &stats, &options.extra_labels);
+        return Ok(metrics_response(
+            body,
+            warp::http::StatusCode::BAD_REQUEST,
+            negotiated,
+        ));
+    };
+
+    let too_small = effective_timeout < options.timeout_policy.min_usable;
+    if too_small && st.note_small_timeout() {
+        warn!(
+            "Scrape budget of {:.3}s is below {:.3}s and will likely time out; \
+             raise scrape_timeout in Prometheus",
+            effective_timeout.as_secs_f64(),
+            options.timeout_policy.min_usable.as_secs_f64()
+        );
+    }
+
+    let (status_code, router_data, mut stats) = collect_scrape(&st, effective_timeout, scope).await;
+    stats.net_status_no_data = options.net_status_no_data;
+    stats.timeout_too_small = too_small;
+    stats.requested_timeout_seconds = requested_timeout(&headers);
+    // Encode all metrics (router + exporter) via prometheus-client once.
+    st.observe_scrape_duration(t0.elapsed().as_secs_f64());
+    let body = encode_body(
+        negotiated.0,
+        router_data.as_ref(),
+        &stats,
+        &options.extra_labels,
+    );
+
+    let mut resp = metrics_response(body, status_code, negotiated);
+    if status_code == warp::http::StatusCode::TOO_MANY_REQUESTS {
+        set_retry_after(&mut resp, effective_timeout);
+    }
+    Ok(resp)
+}
+
+// Ask a saturated client to back off for about one scrape budget (whole seconds, at least 1).
+fn set_retry_after(resp: &mut warp::reply::Response, budget: Duration) {
+    let secs = budget.as_secs_f64().ceil().max(1.0) as u64;
+    resp.headers_mut()
+        .insert(warp::http::header::RETRY_AFTER, secs.into());
+}
+
+// JSON is rendered from the scrape itself; every other format is derived from OpenMetrics text.
+fn encode_body(
+    format: ExpositionFormat,
+    data: Option<&RouterInfoResult>,
+    stats: &ScrapeStats,
+    extra_labels: &[(String, String)],
+) -> String {
+    match format {
+        ExpositionFormat::Json => {
+            encode_metrics_json(data, stats, &version::BUILD_INFO, extra_labels)
+        }
+        _ => encode_metrics_text(data, stats, &version::BUILD_INFO, extra_labels),
+    }
+}
+
+// Wrap an encoded metrics body in the negotiated exposition format and content coding.
+fn metrics_response(
+    body: String,
+    status_code: warp::http::StatusCode,
+    (format, coding): (ExpositionFormat, ContentCoding),
+) -> warp::reply::Response {
+    let body = match format {
+        ExpositionFormat::OpenMetrics | ExpositionFormat::Json => body,
+        ExpositionFormat::PrometheusText => to_prometheus_text(&body),
+        ExpositionFormat::Influx => {
+            let now_ns = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            to_influx_line_protocol(&body, now_ns)
+        }
     };
 
+    let (body, content_encoding) = if coding == ContentCoding::Gzip {
+        match gzip(body.as_bytes()) {
+            Ok(compressed) => (compressed, Some("gzip")),
+            Err(e) => {
+                warn!("gzip compression failed; sending identity body: {}", e);
+                (body.into_bytes(), None)
+            }
+        }
+    } else {
+        (body.into_bytes(), None)
+    };
+
+    let reply = warp::reply::with_status(body, status_code);
+    let reply = warp::reply::with_header(reply, "Content-Type", format.content_type());
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    let reply = warp::reply::with_header(reply, "Vary", "Accept, Accept-Encoding");
+    let mut resp = reply.into_response();
+    if let Some(encoding) = content_encoding {
+        resp.headers_mut().insert(
+            warp::http::header::CONTENT_ENCODING,
+            warp::http::HeaderValue::from_static(encoding),
+        );
+    }
+    resp
+}
+
+// One scrape within `budget`: cache lookup, RouterInfo fetch and outcome bookkeeping.
+pub async fn collect_scrape(
+    st: &I2pControlClient,
+    budget: Duration,
+    scope: MetricsScope,
+) -> (
+    warp::http::StatusCode,
+    Option<RouterInfoResult>,
+    ScrapeStats,
+) {
+    // Serve a fresh-enough cached result instead of hitting the router again.
+    // The cache holds full results only, so lite scrapes neither read nor fill it.
+    let cached = match scope {
+        MetricsScope::Full => st.cached_router_info(),
+        MetricsScope::Lite => None,
+    };
+    let cache_hit = cached.is_some();
+
     // Attempt to fetch target metrics within the overall scrape budget
-    let (status_code, router_data, scrape_error) = match tokio::time::timeout(
-        effective_timeout,
-        st.fetch_router_info(effective_timeout),
-    )
-    .await
-    {
+    let (status_code, router_data, failure) = match cached {
+        Some(data) => (warp::http::StatusCode::OK, Some(data), None),
+        None => scrape_router(st, budget, scope).await,
+    };
+    let (error_kind, error_message) = failure.unzip();
+
+    st.record_scrape_result(router_data.is_some());
+    if let Some(kind) = error_kind {
+        st.counters().record_scrape_error(kind);
+    }
+
+    let stats = ScrapeStats {
+        counters: st.counters(),
+        effective_timeout_seconds: Some(budget.as_secs_f64()),
+        requested_timeout_seconds: None,
+        max_scrape_timeout_seconds: Some(st.options.max_scrape_timeout.as_secs_f64()),
+        last_scrape_error: error_kind.is_some() as u8,
+        error_kind,
+        error_message,
+        cache_hit,
+        router_up: router_data.is_some(),
+        rpc_response_bytes: st.last_response_bytes(),
+        routerinfo_duration_seconds: st.last_routerinfo_seconds(),
+        seconds_since_last_success: st.seconds_since_last_success(),
+        net_status_no_data: false,
+        timeout_too_small: false,
+        target_address: Some(st.target_address.clone()),
+    };
+    (status_code, router_data, stats)
+}
+
+// Fetch RouterInfo under the concurrency limit, mapping failures to an HTTP status.
+async fn scrape_router(
+    st: &I2pControlClient,
+    effective_timeout: Duration,
+    scope: MetricsScope,
+) -> (
+    warp::http::StatusCode,
+    Option<RouterInfoResult>,
+    Option<(ErrorKind, String)>,
+) {
+    let waited = Instant::now();
+    let Ok(_permit) = tokio::time::timeout(effective_timeout, st.acquire_scrape_permit()).await
+    else {
+        warn!(
+            "Too many concurrent scrapes; no permit within {:.3}s",
+            effective_timeout.as_secs_f64()
+        );
+        return (
+            warp::http::StatusCode::TOO_MANY_REQUESTS,
+            None,
+            Some((
+                ErrorKind::Timeout,
+                "too many concurrent scrapes".to_string(),
+            )),
+        );
+    };
+    let budget = effective_timeout.saturating_sub(waited.elapsed());
+
+    let fetch = async {
+        match scope {
+            MetricsScope::Full => st.fetch_router_info(budget).await,
+            MetricsScope::Lite => {
+                let keys = lite_router_info_keys(&st.options.router_info_keys);
+                st.fetch_router_info_keys(budget, &keys, false).await
+            }
+        }
+    };
+
+    match tokio::time::timeout(budget, fetch).await {
         Err(_elapsed) => {
             // Outer scrape budget elapsed; warn with computed budget for observability
             warn!(
                 "Scrape timed out; effective budget {:.3}s",
                 effective_timeout.as_secs_f64()
             );
-            (warp::http::StatusCode::GATEWAY_TIMEOUT, None, 1u8)
+            (
+                warp::http::StatusCode::GATEWAY_TIMEOUT,
+                None,
+                Some((ErrorKind::Timeout, "scrape timed out".to_string())),
+            )
+        }
+        Ok(Ok(data)) => {
+            if scope == MetricsScope::Full {
+                st.store_router_info(&data);
+            }
+            (warp::http::StatusCode::OK, Some(data), None)
         }
-        Ok(Ok(data)) => (warp::http::StatusCode::OK, Some(data), 0u8),
         Ok(Err(err)) => {
             error!("Failed to fetch metrics: {}", err);
-            // If the inner error is a timeout (reqwest/io), surface 504; else 500.
-            let status = if let Some(rpc) = err.downcast_ref::<RpcCallError>() {
-                match rpc {
-                    RpcCallError::Transport(e) if e.is_timeout() => {
-                        warp::http::StatusCode::GATEWAY_TIMEOUT
-                    }
-                    _ => warp::http::StatusCode::INTERNAL_SERVER_ERROR,
-                }
-            } else if let Some(ioe) = err.downcast_ref::<std::io::Error>() {
-                if ioe.kind() == std::io::ErrorKind::TimedOut {
-                    warp::http::StatusCode::GATEWAY_TIMEOUT
-                } else {
-                    warp::http::StatusCode::INTERNAL_SERVER_ERROR
-                }
-            } else {
-                warp::http::StatusCode::INTERNAL_SERVER_ERROR
-            };
-            (status, None, 1u8)
+            let kind = classify_fetch_error(err.as_ref());
+            (
+                fetch_error_status(kind),
+                None,
+                Some((kind, err.to_string())),
+            )
         }
+    }
+}
+
+fn classify_fetch_error(err: &(dyn std::error::Error + 'static)) -> ErrorKind {
+    if let Some(rpc) = err.downcast_ref::<RpcCallError>() {
+        rpc.kind()
+    } else if let Some(ioe) = err.downcast_ref::<std::io::Error>() {
+        if ioe.kind() == std::io::ErrorKind::TimedOut {
+            ErrorKind::Timeout
+        } else {
+            ErrorKind::Transport
+        }
+    } else {
+        ErrorKind::Transport
+    }
+}
+
+// If the inner error is a timeout (reqwest/io), surface 504; else 500.
+fn fetch_error_status(kind: ErrorKind) -> warp::http::StatusCode {
+    if kind == ErrorKind::Timeout {
+        warp::http::StatusCode::GATEWAY_TIMEOUT
+    } else {
+        warp::http::StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+// Adapter that enforces optional basic auth and converts the Reply into a concrete Response
+pub async fn metrics_handler_response(
+    targets: Arc<TargetClients>,
+    options: Arc<RouteOptions>,
+    scope: MetricsScope,
+    query: MetricsQuery,
+    headers: HeaderMap,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    if let Some(resp) = check_basic_auth(&options, &headers) {
+        return Ok(resp);
+    }
+    metrics_handler(targets, options, scope, query, headers).await
+}
+
+// HEAD on a metrics path: the status and headers a GET would carry, without scraping the router.
+pub async fn metrics_head_handler(
+    targets: Arc<TargetClients>,
+    options: Arc<RouteOptions>,
+    query: MetricsQuery,
+    headers: HeaderMap,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    if let Some(resp) = check_basic_auth(&options, &headers) {
+        return Ok(resp);
+    }
+    let (format, coding) = match response_format(query.format.as_deref(), &headers) {
+        Ok(negotiated) => negotiated,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    // Same target and timeout validation as GET; these reject with 400 before any scrape.
+    let st = match targets.resolve(query.target.as_deref()) {
+        Ok(client) => client,
+        Err(_) => return Ok(head_bad_request()),
     };
+    let Ok(hard_max) = request_hard_max(
+        query.max_timeout.as_deref(),
+        st.options.max_scrape_timeout,
+        &options.timeout_policy,
+    ) else {
+        return Ok(head_bad_request());
+    };
+    let status = if effective_timeout(&headers, hard_max, &options.timeout_policy).is_some() {
+        warp::http::StatusCode::OK
+    } else {
+        warp::http::StatusCode::BAD_REQUEST
+    };
+    let reply = warp::reply::with_status(warp::reply(), status);
+    let reply = warp::reply::with_header(reply, "Content-Type", format.content_type());
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    let reply = warp::reply::with_header(reply, "Vary", "Accept, Accept-Encoding");
+    let mut resp = reply.into_response();
+    if coding == ContentCoding::Gzip {
+        resp.headers_mut().insert(
+            warp::http::header::CONTENT_ENCODING,
+            warp::http::HeaderValue::from_static("gzip"),
+        );
+    }
+    Ok(resp)
+}
 
-    // Encode all metrics (router + exporter) via prometheus-client once.
-    let scrape_seconds = t0.elapsed().as_secs_f64();
-    let body = encode_metrics_text(
-        router_data.as_ref(),
-        scrape_seconds,
-        Some(effective_timeout.as_secs_f64()),
-        scrape_error,
-        version::VERSION,
+// Empty 400 mirroring the headers of GET's early target/timeout rejections.
+fn head_bad_request() -> warp::reply::Response {
+    let reply = warp::reply::with_status(warp::reply(), warp::http::StatusCode::BAD_REQUEST);
+    let reply = warp::reply::with_header(reply, "Content-Type", "text/plain; charset=utf-8");
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    reply.into_response()
+}
+
+// 401 challenge when basic auth is configured and the request does not satisfy it.
+fn check_basic_auth(options: &RouteOptions, headers: &HeaderMap) -> Option<warp::reply::Response> {
+    let creds = options.metrics_auth.as_ref()?;
+    let header = headers.get("Authorization").and_then(|v| v.to_str().ok());
+    if creds.verify(header) {
+        return None;
+    }
+    let reply = warp::reply::with_status("Unauthorized", warp::http::StatusCode::UNAUTHORIZED);
+    let reply = warp::reply::with_header(
+        reply,
+        "WWW-Authenticate",
+        "Basic realm=\"i2pd-exporter\", charset=\"UTF-8\"",
     );
+    Some(reply.into_response())
+}
 
-    let reply = warp::reply::with_status(body, status_code);
-    let reply = warp::reply::with_header(reply, "Content-Type", choose_content_type(&headers));
+// Plain-text reply for the debug endpoint's error paths.
+fn debug_text(status: warp::http::StatusCode, msg: String) -> warp::reply::Response {
+    let reply = warp::reply::with_status(msg, status);
     let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
-    Ok(reply)
+    reply.into_response()
 }
 
-// Adapter that converts the Reply into a concrete Response
-pub async fn metrics_handler_response(
-    st: Arc<I2pControlClient>,
+// Troubleshooting view of what the router returns for the configured keys, credentials redacted.
+// Same auth, target and timeout handling as /metrics.
+pub async fn debug_routerinfo_handler(
+    targets: Arc<TargetClients>,
+    options: Arc<RouteOptions>,
+    query: MetricsQuery,
     headers: HeaderMap,
 ) -> Result<warp::reply::Response, warp::Rejection> {
-    let r = metrics_handler(st, headers).await?;
-    Ok(r.into_response())
+    use warp::http::StatusCode;
+
+    if !options.debug_endpoints {
+        return Ok(debug_text(StatusCode::NOT_FOUND, "Not Found".to_string()));
+    }
+    if let Some(resp) = check_basic_auth(&options, &headers) {
+        return Ok(resp);
+    }
+    let st = match targets.resolve(query.target.as_deref()) {
+        Ok(client) => client,
+        Err(err) => return Ok(debug_text(StatusCode::BAD_REQUEST, err.to_string())),
+    };
+    let hard_max = match request_hard_max(
+        query.max_timeout.as_deref(),
+        st.options.max_scrape_timeout,
+        &options.timeout_policy,
+    ) {
+        Ok(hard_max) => hard_max,
+        Err(msg) => return Ok(debug_text(StatusCode::BAD_REQUEST, msg)),
+    };
+    let Some(budget) = effective_timeout(&headers, hard_max, &options.timeout_policy) else {
+        return Ok(debug_text(
+            StatusCode::BAD_REQUEST,
+            "missing or invalid X-Prometheus-Scrape-Timeout-Seconds header".to_string(),
+        ));
+    };
+
+    let waited = Instant::now();
+    let Ok(_permit) = tokio::time::timeout(budget, st.acquire_scrape_permit()).await else {
+        let mut resp = debug_text(
+            StatusCode::TOO_MANY_REQUESTS,
+            "too many concurrent scrapes".to_string(),
+        );
+        set_retry_after(&mut resp, budget);
+        return Ok(resp);
+    };
+    let remaining = budget.saturating_sub(waited.elapsed());
+    let mut raw = match tokio::time::timeout(remaining, st.fetch_router_info_raw(remaining)).await {
+        Err(_elapsed) => {
+            let msg = format!("timed out after {:.3}s", budget.as_secs_f64());
+            return Ok(debug_text(StatusCode::GATEWAY_TIMEOUT, msg));
+        }
+        Ok(Err(err)) => {
+            let status = fetch_error_status(classify_fetch_error(err.as_ref()));
+            return Ok(debug_text(status, err.to_string()));
+        }
+        Ok(Ok(raw)) => raw,
+    };
+    redact_sensitive_fields(&mut raw);
+
+    let body = serde_json::to_string_pretty(&raw).unwrap_or_default();
+    let reply = warp::reply::with_status(body, StatusCode::OK);
+    let reply = warp::reply::with_header(reply, "Content-Type", "application/json");
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    Ok(reply.into_response())
+}
+
+// Report the most recent scrape outcome without contacting the router.
+pub async fn health_handler(
+    targets: Arc<TargetClients>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let last_scrape_success = targets.default_client().last_scrape_succeeded();
+    let status = if last_scrape_success == Some(false) {
+        warp::http::StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        warp::http::StatusCode::OK
+    };
+    let body = serde_json::json!({
+        "status": if status.is_success() { "ok" } else { "unhealthy" },
+        "last_scrape_success": last_scrape_success,
+    });
+    let reply = warp::reply::with_status(warp::reply::json(&body), status);
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    Ok(reply.into_response())
+}
+
+// Budget for the live probe behind /ready.
+const READY_CHECK_BUDGET: Duration = Duration::from_secs(2);
+
+// Actively probe the default router so orchestrators only route scrapes once it answers.
+pub async fn ready_handler(
+    targets: Arc<TargetClients>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let ready = targets
+        .default_client()
+        .check_ready(READY_CHECK_BUDGET)
+        .await;
+    let status = if ready {
+        warp::http::StatusCode::OK
+    } else {
+        warp::http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::json!({ "status": if ready { "ready" } else { "not_ready" } });
+    let reply = warp::reply::with_status(warp::reply::json(&body), status);
+    let reply = warp::reply::with_header(reply, "Cache-Control", "no-store");
+    Ok(reply.into_response())
+}
+
+// Server-level options that shape the route table
+#[derive(Debug, Clone)]
+pub struct RouteOptions {
+    // Full request path of the main scrape route, e.g. `/i2pd/metrics` behind a proxy.
+    pub metrics_path: String,
+    pub metrics_auth: Option<BasicCredentials>,
+    pub extra_labels: Vec<(String, String)>,
+    pub timeout_policy: ScrapeTimeoutPolicy,
+    // Serve /debug/* troubleshooting routes; off by default since they expose raw router data.
+    pub debug_endpoints: bool,
+    // Emit a placeholder net status family when the router cannot be reached.
+    pub net_status_no_data: bool,
+}
+
+impl Default for RouteOptions {
+    fn default() -> Self {
+        RouteOptions {
+            metrics_path: "/metrics".to_string(),
+            metrics_auth: None,
+            extra_labels: Vec::new(),
+            timeout_policy: ScrapeTimeoutPolicy::default(),
+            debug_endpoints: false,
+            net_status_no_data: false,
+        }
+    }
+}
+
+// Match the whole request path against a runtime-configured one.
+fn exact_path(path: Arc<str>) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and_then(move |full: warp::path::FullPath| {
+            let matches = full.as_str() == &*path;
+            async move {
+                if matches {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one()
 }
 
 // Expose a composed routes filter so main can stay lean
 pub fn routes(
-    state: Arc<I2pControlClient>,
+    state: Arc<TargetClients>,
+    options: RouteOptions,
 ) -> impl Filter<Extract = (warp::reply::Response,), Error = warp::Rejection> + Clone {
-    let route_metrics = warp::path("metrics")
+    // Routes are bound to the startup path; everything else is read per request.
+    let metrics_path: Arc<str> = options.metrics_path.as_str().into();
+    state.replace_route_options(options);
+    let options = |state: &Arc<TargetClients>| {
+        let state = state.clone();
+        warp::any().map(move || state.route_options())
+    };
+    let metrics_route = |path: Arc<str>, scope: MetricsScope| {
+        let options = options(&state);
+        let state = state.clone();
+        exact_path(path)
+            .and(warp::get())
+            .and(warp::any().map(move || state.clone()))
+            .and(options)
+            .and(warp::any().map(move || scope))
+            .and(warp::query::<MetricsQuery>())
+            .and(warp::header::headers_cloned())
+            .and_then(metrics_handler_response)
+    };
+    let head_route = |path: Arc<str>| {
+        let options = options(&state);
+        let state = state.clone();
+        exact_path(path)
+            .and(warp::head())
+            .and(warp::any().map(move || state.clone()))
+            .and(options)
+            .and(warp::query::<MetricsQuery>())
+            .and(warp::header::headers_cloned())
+            .and_then(metrics_head_handler)
+    };
+    let route_metrics = metrics_route(metrics_path.clone(), MetricsScope::Full);
+    let route_metrics_lite = metrics_route("/metrics-lite".into(), MetricsScope::Lite);
+    let route_metrics_head = head_route(metrics_path);
+    let route_metrics_lite_head = head_route("/metrics-lite".into());
+
+    let debug_state = state.clone();
+    let route_debug_routerinfo = warp::path!("debug" / "routerinfo")
+        .and(warp::get())
+        .and(warp::any().map(move || debug_state.clone()))
+        .and(options(&state))
+        .and(warp::query::<MetricsQuery>())
+        .and(warp::header::headers_cloned())
+        .and_then(debug_routerinfo_handler);
+
+    let health_state = state.clone();
+    let route_health = warp::path("health")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || health_state.clone()))
+        .and_then(health_handler);
+
+    let route_ready = warp::path("ready")
         .and(warp::path::end())
         .and(warp::get())
         .and(warp::any().map(move || state.clone()))
-        .and(warp::header::headers_cloned())
-        .and_then(metrics_handler_response);
+        .and_then(ready_handler);
 
     let route_404 = warp::path::end().map(|| {
         warp::reply::with_status("Not Found", warp::http::StatusCode::NOT_FOUND).into_response()
     });
 
-    route_metrics.or(route_404).unify()
+    route_metrics
+        .or(route_metrics_lite)
+        .unify()
+        .or(route_metrics_head)
+        .unify()
+        .or(route_metrics_lite_head)
+        .unify()
+        .or(route_health)
+        .unify()
+        .or(route_ready)
+        .unify()
+        .or(route_debug_routerinfo)
+        .unify()
+        .or(route_404)
+        .unify()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::i2pcontrol::targets::HttpSettings;
+    use crate::i2pcontrol::ClientOptions;
+    use crate::metrics::ExporterCounters;
+    use std::io::Read;
+
+    fn timeout_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Prometheus-Scrape-Timeout-Seconds",
+            value.parse().unwrap(),
+        );
+        headers
+    }
+
+    fn policy(margin: f64, threshold: f64) -> ScrapeTimeoutPolicy {
+        ScrapeTimeoutPolicy {
+            margin: Duration::from_secs_f64(margin),
+            margin_threshold: Duration::from_secs_f64(threshold),
+            min_usable: Duration::from_secs(1),
+            default_timeout: None,
+            absolute_max: None,
+        }
+    }
+
+    #[test]
+    fn timeout_default_policy_matches_documented_values() {
+        assert_eq!(ScrapeTimeoutPolicy::default(), policy(0.5, 3.0));
+    }
 
     #[test]
     fn timeout_no_header_is_none() {
         let headers = HeaderMap::new();
-        assert!(effective_timeout(&headers, Duration::from_secs(60)).is_none());
+        let policy = ScrapeTimeoutPolicy::default();
+        assert!(effective_timeout(&headers, Duration::from_secs(60), &policy).is_none());
     }
 
     #[test]
-    fn timeout_smaller_header_wins_with_margin_above_threshold() {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            "X-Prometheus-Scrape-Timeout-Seconds",
-            "3.1".parse().unwrap(),
+    fn timeout_no_header_uses_default_when_configured() {
+        let headers = HeaderMap::new();
+        let with_default = ScrapeTimeoutPolicy {
+            default_timeout: Some(Duration::from_secs(10)),
+            ..ScrapeTimeoutPolicy::default()
+        };
+        let eff = effective_timeout(&headers, Duration::from_secs(60), &with_default);
+        assert_eq!(eff, Some(Duration::from_secs(10)));
+        // Still bounded by the hard cap.
+        let eff = effective_timeout(&headers, Duration::from_secs(4), &with_default);
+        assert_eq!(eff, Some(Duration::from_secs(4)));
+        // The header wins over the default when present.
+        let eff = effective_timeout(&timeout_header("2"), Duration::from_secs(60), &with_default);
+        assert_eq!(eff, Some(Duration::from_secs(2)));
+        // An invalid header is still rejected.
+        let eff = effective_timeout(
+            &timeout_header("abc"),
+            Duration::from_secs(60),
+            &with_default,
         );
-        // 3.1 > 3.0 -> apply margin: 3.1 - 0.5 = 2.6s
-        let eff = effective_timeout(&headers, Duration::from_secs(60)).unwrap();
-        assert!((eff.as_secs_f64() - 2.6).abs() < 1e-9);
+        assert!(eff.is_none());
     }
 
     #[test]
-    fn timeout_large_header_is_capped_by_max() {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            "X-Prometheus-Scrape-Timeout-Seconds",
-            "30.0".parse().unwrap(),
+    fn timeout_header_cases() {
+        // (header, max secs, margin, threshold, expected)
+        let cases = [
+            // 3.1 > 3.0 -> apply margin: 3.1 - 0.5 = 2.6s
+            ("3.1", 60, 0.5, 3.0, 2.6),
+            // 30.0 - 0.5 = 29.5s, but cap at 10s
+            ("30.0", 10, 0.5, 3.0, 10.0),
+            // 0.2 <= 3.0 -> no margin; remains 0.2s
+            ("0.2", 60, 0.5, 3.0, 0.2),
+            // -5.0 -> clamped to 0.1s
+            ("-5", 60, 0.5, 3.0, 0.1),
+            // Exactly at the threshold -> no margin
+            ("3.0", 60, 0.5, 3.0, 3.0),
+        ];
+        for (header, max, margin, threshold, expected) in cases {
+            let eff = effective_timeout(
+                &timeout_header(header),
+                Duration::from_secs(max),
+                &policy(margin, threshold),
+            )
+            .unwrap();
+            assert!(
+                (eff.as_secs_f64() - expected).abs() < 1e-9,
+                "header {} -> {:?}, expected {}",
+                header,
+                eff,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn timeout_custom_margin_and_threshold() {
+        let custom = policy(2.0, 1.0);
+        let eff = effective_timeout(&timeout_header("10"), Duration::from_secs(60), &custom);
+        assert_eq!(eff, Some(Duration::from_secs(8)));
+        // 1.5 > 1.0 -> 1.5 - 2.0 is negative, still clamped to 0.1s
+        let eff = effective_timeout(&timeout_header("1.5"), Duration::from_secs(60), &custom);
+        assert_eq!(eff, Some(Duration::from_millis(100)));
+        // A zero margin passes the header through.
+        let eff = effective_timeout(
+            &timeout_header("10"),
+            Duration::from_secs(60),
+            &policy(0.0, 0.0),
+        );
+        assert_eq!(eff, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn timeout_header_non_numeric_is_none() {
+        let policy = ScrapeTimeoutPolicy::default();
+        let headers = timeout_header("not-a-number");
+        assert!(effective_timeout(&headers, Duration::from_secs(60), &policy).is_none());
+    }
+    // No default cap test anymore
+
+    fn test_state() -> Arc<TargetClients> {
+        test_state_for("http://127.0.0.1:1/jsonrpc".to_string(), 4)
+    }
+
+    fn test_state_for(url: String, max_concurrent_scrapes: usize) -> Arc<TargetClients> {
+        let default = Arc::new(I2pControlClient::new(
+            reqwest::Client::new(),
+            url,
+            ClientOptions {
+                max_scrape_timeout: Duration::from_secs(60),
+                max_concurrent_scrapes,
+                rpc_retries: 0,
+                ..ClientOptions::default()
+            },
+            ExporterCounters::default(),
+        ));
+        Arc::new(TargetClients::new(default, false, HttpSettings::default()))
+    }
+
+    #[tokio::test]
+    async fn health_is_ok_before_first_scrape() {
+        let resp = warp::test::request()
+            .path("/health")
+            .reply(&routes(test_state(), RouteOptions::default()))
+            .await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.body().as_ref(),
+            br#"{"last_scrape_success":null,"status":"ok"}"#
         );
-        // 30.0 - 0.5 = 29.5s, but cap at 10s
-        let eff = effective_timeout(&headers, Duration::from_secs(10)).unwrap();
-        assert!((eff.as_secs_f64() - 10.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn health_reflects_last_scrape_result() {
+        let state = test_state();
+        let filter = routes(state.clone(), RouteOptions::default());
+        let default = state.default_client();
+
+        default.record_scrape_result(false);
+        let resp = warp::test::request().path("/health").reply(&filter).await;
+        assert_eq!(resp.status(), 503);
+        assert_eq!(
+            resp.body().as_ref(),
+            br#"{"last_scrape_success":false,"status":"unhealthy"}"#
+        );
+
+        default.record_scrape_result(true);
+        let resp = warp::test::request().path("/health").reply(&filter).await;
+        assert_eq!(resp.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn missing_timeout_header_still_reports_self_metrics() {
+        let state = test_state();
+        let filter = routes(state.clone(), RouteOptions::default());
+        let resp = warp::test::request().path("/metrics").reply(&filter).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("i2pd_exporter_last_scrape_error 1.0\n"));
+        assert!(body.contains(
+            "i2pd_exporter_last_scrape_error_info{message=\"missing or invalid \
+             X-Prometheus-Scrape-Timeout-Seconds header\"} 1.0\n"
+        ));
+        assert!(body.contains("i2pd_exporter_missing_timeout_header_total 1\n"));
+        assert!(body.contains("i2p_router_up 0.0\n"));
+        assert!(!body.contains("i2pd_exporter_effective_scrape_timeout_seconds"));
+        assert_eq!(state.default_client().last_scrape_succeeded(), Some(false));
     }
 
     #[test]
-    fn timeout_small_header_kept_no_margin() {
+    fn request_hard_max_respects_the_absolute_ceiling() {
+        let cap = Duration::from_secs(60);
+        let policy = ScrapeTimeoutPolicy {
+            absolute_max: Some(Duration::from_secs(120)),
+            ..ScrapeTimeoutPolicy::default()
+        };
+        assert_eq!(request_hard_max(None, cap, &policy), Ok(cap));
+        assert_eq!(
+            request_hard_max(Some("90"), cap, &policy),
+            Ok(Duration::from_secs(90))
+        );
+        assert_eq!(
+            request_hard_max(Some("5"), cap, &policy),
+            Ok(Duration::from_secs(5))
+        );
+        assert!(request_hard_max(Some("121"), cap, &policy).is_err());
+        assert!(request_hard_max(Some("0"), cap, &policy).is_err());
+        assert!(request_hard_max(Some("soon"), cap, &policy).is_err());
+        // Without a ceiling the override can only lower the cap.
+        let lower_only = ScrapeTimeoutPolicy::default();
+        assert!(request_hard_max(Some("90"), cap, &lower_only).is_err());
+    }
+
+    #[tokio::test]
+    async fn max_timeout_query_replaces_the_hard_cap() {
+        let options = RouteOptions {
+            timeout_policy: ScrapeTimeoutPolicy {
+                absolute_max: Some(Duration::from_secs(120)),
+                ..ScrapeTimeoutPolicy::default()
+            },
+            ..RouteOptions::default()
+        };
+        let filter = routes(test_state(), options);
+
+        let resp = warp::test::request()
+            .path("/metrics?max_timeout=90")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "100")
+            .reply(&filter)
+            .await;
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("i2pd_exporter_effective_scrape_timeout_seconds 90.0\n"));
+
+        let resp = warp::test::request()
+            .path("/metrics?max_timeout=300")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "100")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn missing_timeout_header_uses_default_timeout_when_configured() {
+        let options = RouteOptions {
+            timeout_policy: ScrapeTimeoutPolicy {
+                default_timeout: Some(Duration::from_secs(1)),
+                ..ScrapeTimeoutPolicy::default()
+            },
+            ..RouteOptions::default()
+        };
+        let resp = warp::test::request()
+            .path("/metrics")
+            .reply(&routes(test_state(), options))
+            .await;
+
+        // The scrape proceeds (the test router is unreachable), rather than a 400.
+        assert_ne!(resp.status(), 400);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("i2pd_exporter_effective_scrape_timeout_seconds 1.0\n"));
+        assert!(body.contains("i2pd_exporter_max_scrape_timeout_seconds 60.0\n"));
+        assert!(body.contains("i2pd_exporter_missing_timeout_header_total 0\n"));
+    }
+
+    #[tokio::test]
+    async fn scrape_duration_histogram_counts_every_scrape() {
+        let filter = routes(test_state(), RouteOptions::default());
+        let mut body = String::new();
+        for _ in 0..3 {
+            let resp = warp::test::request()
+                .path("/metrics")
+                .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+                .reply(&filter)
+                .await;
+            body = String::from_utf8_lossy(resp.body()).into_owned();
+        }
+        assert!(body.contains("i2pd_exporter_scrape_duration_seconds_count 3\n"));
+    }
+
+    #[tokio::test]
+    async fn failed_scrapes_accumulate_in_error_counter() {
+        let filter = routes(test_state(), RouteOptions::default());
+        let mut body = String::new();
+        for _ in 0..2 {
+            let resp = warp::test::request()
+                .path("/metrics")
+                .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+                .reply(&filter)
+                .await;
+            body = String::from_utf8_lossy(resp.body()).into_owned();
+        }
+        // The test router port refuses connections.
+        assert!(body.contains("i2pd_exporter_scrape_errors_total{kind=\"transport\"} 2\n"));
+        assert!(body.contains("i2pd_exporter_scrape_errors_total{kind=\"timeout\"} 0\n"));
+    }
+
+    #[tokio::test]
+    async fn tiny_scrape_budget_is_flagged() {
+        let state = test_state();
+        let filter = routes(state.clone(), RouteOptions::default());
+        for (header, flagged) in [("0.2", "1.0"), ("2", "0.0"), ("0.1", "1.0")] {
+            let resp = warp::test::request()
+                .path("/metrics")
+                .header("X-Prometheus-Scrape-Timeout-Seconds", header)
+                .reply(&filter)
+                .await;
+            let body = String::from_utf8_lossy(resp.body());
+            assert!(body.contains(&format!(
+                "i2pd_exporter_scrape_timeout_too_small {}\n",
+                flagged
+            )));
+        }
+        // Already logged by the first tiny scrape.
+        assert!(!state.default_client().note_small_timeout());
+    }
+
+    #[tokio::test]
+    async fn timeout_margin_reports_what_was_shaved_off() {
+        let filter = routes(test_state(), RouteOptions::default());
+        // Above the 3s threshold the 0.5s margin applies; 90s is also capped at the 60s max.
+        for (header, margin) in [("10", "0.5"), ("2", "0.0"), ("90", "30.0")] {
+            let resp = warp::test::request()
+                .path("/metrics")
+                .header("X-Prometheus-Scrape-Timeout-Seconds", header)
+                .reply(&filter)
+                .await;
+            let body = String::from_utf8_lossy(resp.body());
+            assert!(
+                body.contains(&format!(
+                    "i2pd_exporter_scrape_timeout_margin_seconds {}\n",
+                    margin
+                )),
+                "header {}",
+                header
+            );
+        }
+
+        // Without the header (default timeout in use) there is no requested value to compare.
+        let options = RouteOptions {
+            timeout_policy: ScrapeTimeoutPolicy {
+                default_timeout: Some(Duration::from_secs(5)),
+                ..ScrapeTimeoutPolicy::default()
+            },
+            ..RouteOptions::default()
+        };
+        let resp = warp::test::request()
+            .path("/metrics")
+            .reply(&routes(test_state(), options))
+            .await;
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body.contains("i2pd_exporter_effective_scrape_timeout_seconds 5.0\n"));
+        assert!(!body.contains("i2pd_exporter_scrape_timeout_margin"));
+    }
+
+    #[tokio::test]
+    async fn target_info_names_the_scraped_target() {
+        let filter = routes(test_state(), RouteOptions::default());
+        for (path, address) in [
+            ("/metrics", "http://127.0.0.1:1"),
+            ("/metrics?target=http://127.0.0.1:2", "http://127.0.0.1:2"),
+        ] {
+            let resp = warp::test::request()
+                .path(path)
+                .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+                .reply(&filter)
+                .await;
+            let body = String::from_utf8_lossy(resp.body());
+            assert!(body.contains(&format!(
+                "i2pd_exporter_target_info{{address=\"{}\",api_version=\"1\"}} 1.0\n",
+                address
+            )));
+        }
+    }
+
+    #[tokio::test]
+    async fn target_counters_persist_past_the_cache_limit() {
+        let state = test_state();
+        let filter = routes(state.clone(), RouteOptions::default());
+        // Without the timeout header each scrape counts once and never touches the network.
+        for port in 2..100 {
+            for target in [
+                "http://127.0.0.1:1234".to_string(),
+                format!("http://127.0.0.1:{}", port),
+            ] {
+                let resp = warp::test::request()
+                    .path(&format!("/metrics?target={}", target))
+                    .reply(&filter)
+                    .await;
+                assert_eq!(resp.status(), 400);
+            }
+        }
+        let kept = state.resolve(Some("http://127.0.0.1:1234")).unwrap();
+        assert_eq!(kept.counters().missing_timeout_headers.get(), 98);
+        let evicted = state.resolve(Some("http://127.0.0.1:2")).unwrap();
+        assert_eq!(evicted.counters().missing_timeout_headers.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn metrics_rejects_invalid_target() {
+        let resp = warp::test::request()
+            .path("/metrics?target=http://10.0.0.5:7650")
+            .header("Accept", "application/openmetrics-text")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "10")
+            .reply(&routes(test_state(), RouteOptions::default()))
+            .await;
+        assert_eq!(resp.status(), 400);
+        assert_eq!(resp.headers()["content-type"], "text/plain; charset=utf-8");
+    }
+
+    #[tokio::test]
+    async fn head_answers_with_get_headers_without_scraping() {
+        let state = test_state();
+        let filter = routes(state.clone(), RouteOptions::default());
+        for path in ["/metrics", "/metrics-lite"] {
+            let resp = warp::test::request()
+                .method("HEAD")
+                .path(path)
+                .header("Accept-Encoding", "gzip")
+                .header("X-Prometheus-Scrape-Timeout-Seconds", "10")
+                .reply(&filter)
+                .await;
+            assert_eq!(resp.status(), 200, "{}", path);
+            assert!(resp.body().is_empty());
+            assert_eq!(resp.headers()["content-type"], OM_CONTENT_TYPE);
+            assert_eq!(resp.headers()["content-encoding"], "gzip");
+            assert_eq!(resp.headers()["cache-control"], "no-store");
+        }
+        assert_eq!(state.default_client().counters().rpc_requests.get(), 0);
+
+        // GET answers 400 without the timeout header; HEAD must report the same status.
+        let resp = warp::test::request()
+            .method("HEAD")
+            .path("/metrics")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 400);
+        assert!(resp.body().is_empty());
+        assert_eq!(resp.headers()["content-type"], OM_CONTENT_TYPE);
+        assert_eq!(state.default_client().counters().rpc_requests.get(), 0);
+
+        let resp = warp::test::request()
+            .method("HEAD")
+            .path("/metrics?max_timeout=abc")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "10")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 400);
+
+        let resp = warp::test::request()
+            .method("HEAD")
+            .path("/metrics?format=graphite")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 400);
+
+        let options = RouteOptions {
+            metrics_auth: Some(BasicCredentials {
+                username: "prom".to_string(),
+                password: "s3cret".to_string(),
+            }),
+            ..RouteOptions::default()
+        };
+        let resp = warp::test::request()
+            .method("HEAD")
+            .path("/metrics")
+            .reply(&routes(test_state(), options))
+            .await;
+        assert_eq!(resp.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn metrics_requires_basic_auth_when_configured() {
+        let options = RouteOptions {
+            metrics_auth: Some(BasicCredentials {
+                username: "prom".to_string(),
+                password: "s3cret".to_string(),
+            }),
+            ..RouteOptions::default()
+        };
+        let filter = routes(test_state(), options);
+
+        let resp = warp::test::request()
+            .path("/metrics")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "10")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 401);
+        assert_eq!(
+            resp.headers()["WWW-Authenticate"],
+            "Basic realm=\"i2pd-exporter\", charset=\"UTF-8\""
+        );
+
+        // base64("prom:wrong")
+        let resp = warp::test::request()
+            .path("/metrics")
+            .header("Authorization", "Basic cHJvbTp3cm9uZw==")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 401);
+
+        // Correct credentials pass auth and reach the header check (400 without it).
+        let resp = warp::test::request()
+            .path("/metrics")
+            .header("Authorization", "Basic cHJvbTpzM2NyZXQ=")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[test]
+    fn format_defaults_to_openmetrics() {
         let mut headers = HeaderMap::new();
+        assert_eq!(choose_format(&headers), Some(ExpositionFormat::OpenMetrics));
+        headers.insert("Accept", "*/*".parse().unwrap());
+        assert_eq!(choose_format(&headers), Some(ExpositionFormat::OpenMetrics));
         headers.insert(
-            "X-Prometheus-Scrape-Timeout-Seconds",
-            "0.2".parse().unwrap(),
+            "Accept",
+            "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5"
+                .parse()
+                .unwrap(),
         );
-        // 0.2 <= 3.0 -> no margin; remains 0.2s
-        let eff = effective_timeout(&headers, Duration::from_secs(60)).unwrap();
-        assert!((eff.as_secs_f64() - 0.2).abs() < 1e-9);
+        assert_eq!(choose_format(&headers), Some(ExpositionFormat::OpenMetrics));
     }
 
     #[test]
-    fn timeout_header_negative_value_clamped_to_min() {
+    fn format_uses_prometheus_text_for_text_plain_clients() {
         let mut headers = HeaderMap::new();
-        headers.insert("X-Prometheus-Scrape-Timeout-Seconds", "-5".parse().unwrap());
-        // -5.0 - 0.5 => clamped to 0.1s, min with default -> 0.1s
-        let eff = effective_timeout(&headers, Duration::from_secs(60)).unwrap();
-        assert!((eff.as_secs_f64() - 0.1).abs() < 1e-9);
+        headers.insert("Accept", "text/plain;version=0.0.4".parse().unwrap());
+        assert_eq!(
+            choose_format(&headers),
+            Some(ExpositionFormat::PrometheusText)
+        );
+        assert_eq!(
+            choose_format(&headers).unwrap().content_type(),
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_negotiates_prometheus_text() {
+        let resp = warp::test::request()
+            .path("/metrics")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .header("Accept", "text/plain")
+            .reply(&routes(test_state(), RouteOptions::default()))
+            .await;
+        assert_eq!(
+            resp.headers()["Content-Type"],
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+        assert!(!std::str::from_utf8(resp.body()).unwrap().contains("# EOF"));
     }
 
     #[test]
-    fn timeout_header_non_numeric_is_none() {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            "X-Prometheus-Scrape-Timeout-Seconds",
-            "not-a-number".parse().unwrap(),
+    fn format_honours_q_values() {
+        let format = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("Accept", accept.parse().unwrap());
+            choose_format(&headers)
+        };
+        assert_eq!(
+            format("application/openmetrics-text;q=0, text/plain"),
+            Some(ExpositionFormat::PrometheusText)
+        );
+        assert_eq!(
+            format("application/openmetrics-text;q=0.2, text/plain;q=0.9"),
+            Some(ExpositionFormat::PrometheusText)
+        );
+        assert_eq!(
+            format("text/*;q=0.5, application/*;q=0.8"),
+            Some(ExpositionFormat::OpenMetrics)
         );
-        assert!(effective_timeout(&headers, Duration::from_secs(60)).is_none());
+        // The exact type wins over the wildcard that would otherwise allow it.
+        assert_eq!(
+            format("*/*, application/openmetrics-text;q=0"),
+            Some(ExpositionFormat::PrometheusText)
+        );
+        // Formats the client never mentions remain a fallback.
+        assert_eq!(
+            format("application/json"),
+            Some(ExpositionFormat::OpenMetrics)
+        );
+        assert_eq!(
+            format("application/openmetrics-text;q=0"),
+            Some(ExpositionFormat::PrometheusText)
+        );
+        assert_eq!(
+            format("application/openmetrics-text;q=0, text/plain;q=0"),
+            None
+        );
+        assert_eq!(format("*/*;q=0"), None);
+    }
+
+    #[test]
+    fn encoding_parses_accept_encoding() {
+        let encoding = |value: Option<&str>| {
+            let mut headers = HeaderMap::new();
+            if let Some(v) = value {
+                headers.insert("Accept-Encoding", v.parse().unwrap());
+            }
+            choose_encoding(&headers)
+        };
+        assert_eq!(encoding(None), Some(ContentCoding::Identity));
+        assert_eq!(encoding(Some("gzip")), Some(ContentCoding::Gzip));
+        assert_eq!(
+            encoding(Some("deflate, GZIP;q=0.5")),
+            Some(ContentCoding::Gzip)
+        );
+        assert_eq!(
+            encoding(Some("gzip;q=0, identity")),
+            Some(ContentCoding::Identity)
+        );
+        assert_eq!(encoding(Some("identity")), Some(ContentCoding::Identity));
+        assert_eq!(encoding(Some("*")), Some(ContentCoding::Gzip));
+        assert_eq!(encoding(Some("identity;q=0")), None);
+        assert_eq!(encoding(Some("*;q=0")), None);
+        assert_eq!(encoding(Some("gzip;q=0, *;q=0")), None);
+    }
+
+    #[tokio::test]
+    async fn format_query_selects_influx_line_protocol() {
+        let filter = routes(test_state(), RouteOptions::default());
+        let resp = warp::test::request()
+            .path("/metrics?format=influx")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .header("Accept", "application/openmetrics-text")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.headers()["content-type"], INFLUX_CONTENT_TYPE);
+        let body = String::from_utf8_lossy(resp.body());
+        assert!(body
+            .lines()
+            .any(|l| l.starts_with("i2p_router_up value=0 ")));
+        assert!(!body.contains("# "));
+
+        let resp = warp::test::request()
+            .path("/metrics?format=graphite")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn format_query_selects_json() {
+        let options = RouteOptions {
+            extra_labels: vec![("site".to_string(), "a".to_string())],
+            ..RouteOptions::default()
+        };
+        let resp = warp::test::request()
+            .path("/metrics?format=json")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .reply(&routes(test_state(), options))
+            .await;
+        assert_eq!(resp.headers()["content-type"], JSON_CONTENT_TYPE);
+        let doc: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+        assert!(doc["router"].is_null());
+        assert_eq!(doc["exporter"]["router_up"], false);
+        assert_eq!(doc["exporter"]["last_scrape_error"], true);
+        assert_eq!(doc["exporter"]["version"], version::VERSION);
+        assert_eq!(doc["labels"]["site"], "a");
+    }
+
+    #[tokio::test]
+    async fn metrics_path_is_configurable() {
+        let options = RouteOptions {
+            metrics_path: "/i2pd/metrics".to_string(),
+            ..RouteOptions::default()
+        };
+        let filter = routes(test_state(), options);
+        let resp = warp::test::request()
+            .path("/i2pd/metrics")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .reply(&filter)
+            .await;
+        assert!(String::from_utf8_lossy(resp.body()).contains("i2p_router_up"));
+
+        for path in ["/metrics", "/i2pd/metrics/extra", "/i2pd"] {
+            let resp = warp::test::request().path(path).reply(&filter).await;
+            assert_eq!(resp.status(), 404, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn metrics_returns_406_when_every_format_is_refused() {
+        let resp = warp::test::request()
+            .path("/metrics")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .header("Accept", "application/openmetrics-text;q=0, text/plain;q=0")
+            .reply(&routes(test_state(), RouteOptions::default()))
+            .await;
+        assert_eq!(resp.status(), 406);
+    }
+
+    #[test]
+    fn gzip_round_trips_metrics_text() {
+        let text = "# TYPE up gauge\nup 1\n# EOF\n";
+        let compressed = gzip(text.as_bytes()).unwrap();
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[tokio::test]
+    async fn metrics_gzip_body_matches_identity_body() {
+        let filter = routes(test_state(), RouteOptions::default());
+        // Failed scrapes still render exporter metrics; drop the lines that vary between scrapes
+        // and sort, since label families iterate in hash order.
+        let strip_duration = |text: &str| {
+            let mut lines: Vec<_> = text
+                .lines()
+                .filter(|l| {
+                    !l.starts_with("i2pd_exporter_scrape_duration_seconds")
+                        && !l.starts_with("i2pd_exporter_scrape_timestamp_seconds ")
+                        && !l.starts_with("i2pd_exporter_rpc_requests_total ")
+                        && !l.starts_with("i2pd_exporter_scrape_errors_total")
+                })
+                .collect();
+            lines.sort_unstable();
+            lines.join("\n")
+        };
+
+        let plain = warp::test::request()
+            .path("/metrics")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .reply(&filter)
+            .await;
+        assert!(plain.headers().get("Content-Encoding").is_none());
+
+        let zipped = warp::test::request()
+            .path("/metrics")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "2")
+            .header("Accept-Encoding", "gzip")
+            .reply(&filter)
+            .await;
+        assert_eq!(zipped.headers()["Content-Encoding"], "gzip");
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(zipped.body().as_ref())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        let identity = std::str::from_utf8(plain.body()).unwrap();
+        assert_eq!(strip_duration(&decoded), strip_duration(identity));
+    }
+
+    // Accepts connections and never answers, holding scrapes until their budget expires.
+    async fn black_hole_target() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((sock, _)) = listener.accept().await {
+                held.push(sock);
+            }
+        });
+        format!("http://{}/jsonrpc", addr)
+    }
+
+    #[tokio::test]
+    async fn overflow_scrapes_are_rejected_when_permits_exhausted() {
+        let filter = routes(
+            test_state_for(black_hole_target().await, 1),
+            RouteOptions::default(),
+        );
+        let request = |timeout: &'static str| {
+            warp::test::request()
+                .path("/metrics")
+                .header("X-Prometheus-Scrape-Timeout-Seconds", timeout)
+                .reply(&filter)
+        };
+
+        let holder = request("1.5");
+        let overflow = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let started = Instant::now();
+            let (a, b) = tokio::join!(request("0.3"), request("0.3"));
+            (a, b, started.elapsed())
+        };
+        let (held, (a, b, overflow_elapsed)) = tokio::join!(holder, overflow);
+
+        assert_eq!(a.status(), 429);
+        assert_eq!(b.status(), 429);
+        // 0.3s budget rounds up to one second.
+        assert_eq!(a.headers()["Retry-After"], "1");
+        assert!(overflow_elapsed < Duration::from_secs(1));
+        assert_eq!(held.status(), 504);
     }
-    // No default cap test anymore
 }
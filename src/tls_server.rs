@@ -0,0 +1,91 @@
+// HTTPS serving for the metrics endpoint (Warp 0.4 has no built-in TLS acceptor)
+
+use std::path::Path;
+use std::sync::Arc;
+
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use log::debug;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use warp::{Filter, Reply};
+
+// Load a PEM certificate chain and private key into a rustls server config.
+pub fn load_tls_config(
+    cert_path: &Path,
+    key_path: &Path,
+) -> Result<Arc<ServerConfig>, Box<dyn std::error::Error + Send + Sync>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| {
+            format!(
+                "Cannot load METRICS_TLS_CERT '{}': {}",
+                cert_path.display(),
+                e
+            )
+        })?;
+    if certs.is_empty() {
+        return Err(format!(
+            "METRICS_TLS_CERT '{}' contains no certificates",
+            cert_path.display()
+        )
+        .into());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| {
+        format!(
+            "Cannot load METRICS_TLS_KEY '{}': {}",
+            key_path.display(),
+            e
+        )
+    })?;
+
+    // Pin the provider explicitly; more than one may be compiled in via dependencies.
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid metrics TLS certificate/key pair: {}", e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+// Accept TLS connections and serve the Warp filter over HTTP/1.1.
+pub async fn serve_tls<F>(filter: F, listener: TcpListener, tls: Arc<ServerConfig>)
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let acceptor = TlsAcceptor::from(tls);
+    loop {
+        let (tcp, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                debug!("accept error: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let svc = TowerToHyperService::new(warp::service(filter.clone()));
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(tcp).await {
+                Ok(s) => s,
+                Err(e) => {
+                    debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .http1()
+                .serve_connection(TokioIo::new(stream), svc)
+                .await
+            {
+                debug!("connection error from {}: {}", peer, e);
+            }
+        });
+    }
+}
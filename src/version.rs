@@ -1,3 +1,37 @@
 // Centralized exporter version constant
 // Pulled from Cargo package version at compile time
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Git revision the binary was built from ("unknown" outside a checkout); set by build.rs
+pub const GIT_SHA: &str = env!("I2PD_EXPORTER_GIT_SHA");
+
+// Compiler version and cargo profile ("debug"/"release") of this build; set by build.rs
+pub const RUSTC_VERSION: &str = env!("I2PD_EXPORTER_RUSTC_VERSION");
+pub const BUILD_PROFILE: &str = env!("I2PD_EXPORTER_BUILD_PROFILE");
+
+// Machine-readable `--version-json` output.
+pub fn version_json() -> String {
+    serde_json::json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": VERSION,
+        "git_sha": GIT_SHA,
+        "rustc": RUSTC_VERSION,
+    })
+    .to_string()
+}
+
+// Labels for `i2pd_exporter_build_info` and `i2pd_exporter_info`.
+#[derive(Debug, Clone, Copy)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub revision: &'static str,
+    pub rustc_version: &'static str,
+    pub build_profile: &'static str,
+}
+
+pub const BUILD_INFO: BuildInfo = BuildInfo {
+    version: VERSION,
+    revision: GIT_SHA,
+    rustc_version: RUSTC_VERSION,
+    build_profile: BUILD_PROFILE,
+};
@@ -1,4 +1,7 @@
+mod common;
+
 use assert_cmd::prelude::*;
+use common::{router_info_ok, MockReply, MockServer};
 use predicates::prelude::*;
 use std::process::Command;
 
@@ -11,3 +14,233 @@ fn prints_version() {
         .success()
         .stdout(predicate::str::contains("i2pd-exporter"));
 }
+
+#[test]
+fn prints_version_json() {
+    let out = Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+        .arg("--version-json")
+        // Ignored: version info does not depend on a valid configuration.
+        .args(["--metrics-path", "no-leading-slash"])
+        .env_remove("I2PD_EXPORTER_CONFIG")
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let info: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(info["name"], "i2pd-exporter");
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    for key in ["git_sha", "rustc"] {
+        assert!(!info[key].as_str().unwrap().is_empty(), "{}", key);
+    }
+}
+
+fn dry_run(address: String) -> std::process::Output {
+    Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+        .args(["--dry-run", "--i2pcontrol-address", &address])
+        .args(["--max-scrape-timeout-seconds", "5"])
+        .env_remove("I2PD_EXPORTER_CONFIG")
+        .output()
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dry_run_prints_metrics_and_exits_zero() {
+    let ok = router_info_ok(serde_json::json!({ "i2p.router.status": "1" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+
+    let out = tokio::task::spawn_blocking(move || dry_run(server.url.clone()))
+        .await
+        .unwrap();
+
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("i2p_router_status 1.0\n"));
+    assert!(stdout.contains("i2p_router_up 1.0\n"));
+    assert!(stdout.ends_with("# EOF\n"));
+}
+
+#[test]
+fn dry_run_fails_when_router_is_unreachable() {
+    let out = dry_run("http://127.0.0.1:1".to_string());
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn startup_fails_when_any_listen_addr_cannot_bind() {
+    let busy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let busy_addr = busy.local_addr().unwrap();
+
+    Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+        .args([
+            "--metrics-listen-addr",
+            &format!("127.0.0.1:0,{}", busy_addr),
+        ])
+        .env_remove("I2PD_EXPORTER_CONFIG")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "Cannot bind {}",
+            busy_addr
+        )));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn reuse_port_lets_a_second_instance_bind_the_same_port() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let spawn = || {
+        Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+            .args(["--metrics-listen-addr", &format!("127.0.0.1:{}", port)])
+            .arg("--reuse-port")
+            .env_remove("I2PD_EXPORTER_CONFIG")
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .unwrap()
+    };
+    let mut old = spawn();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    let mut new = spawn();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    // Both are still serving: neither exited on a bind failure.
+    let alive = [old.try_wait().unwrap(), new.try_wait().unwrap()];
+    let _ = old.kill();
+    let _ = new.kill();
+    let _ = old.wait();
+    let _ = new.wait();
+    assert_eq!(alive, [None, None]);
+}
+
+#[test]
+fn wait_for_router_exits_nonzero_when_router_never_answers() {
+    Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+        .args(["--i2pcontrol-address", "http://127.0.0.1:1"])
+        .args(["--metrics-listen-addr", "127.0.0.1:0"])
+        .args(["--wait-for-router", "--startup-timeout-seconds", "1"])
+        .env_remove("I2PD_EXPORTER_CONFIG")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Router did not answer within 1s"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wait_for_router_serves_once_the_router_answers() {
+    let ok = router_info_ok(serde_json::json!({ "i2p.router.status": "1" }));
+    let server = MockServer::start(vec![
+        MockReply::Http(503, String::new()),
+        MockReply::Http(200, ok),
+    ])
+    .await;
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut child = Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+        .args(["--i2pcontrol-address", &server.url])
+        .args(["--metrics-listen-addr", &format!("127.0.0.1:{}", port)])
+        .args(["--wait-for-router", "--rpc-retries", "0"])
+        .env_remove("I2PD_EXPORTER_CONFIG")
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut listening = false;
+    for _ in 0..100 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .is_ok()
+        {
+            listening = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let still_running = child.try_wait().unwrap().is_none();
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(listening && still_running);
+    // The failed first attempt was retried before the port was bound.
+    assert!(server.hits() >= 2);
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn sighup_reloads_the_i2pcontrol_target() {
+    let ok = router_info_ok(serde_json::json!({ "i2p.router.status": "1" }));
+    let old = MockServer::start(vec![MockReply::Http(200, ok.clone())]).await;
+    let new = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let config =
+        std::env::temp_dir().join(format!("i2pd-exporter-reload-{}.toml", std::process::id()));
+    let point_at = |url: &str, password: &str, margin: f64| {
+        let toml = format!(
+            "i2pcontrol_address = \"{}\"\nmetrics_auth_username = \"prom\"\n\
+             metrics_auth_password = \"{}\"\nscrape_timeout_margin_seconds = {:?}\n",
+            url, password, margin
+        );
+        std::fs::write(&config, toml).unwrap()
+    };
+    point_at(&old.url, "old", 0.5);
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut child = Command::new(assert_cmd::cargo_bin!("i2pd-exporter"))
+        .arg("--config")
+        .arg(&config)
+        .args(["--metrics-listen-addr", &format!("127.0.0.1:{}", port)])
+        .args(["--rpc-retries", "0", "--cache-ttl-seconds", "0"])
+        .env_remove("I2PD_EXPORTER_CONFIG")
+        .env_remove("I2PCONTROL_ADDRESS")
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let http = reqwest::Client::new();
+    let scrape = |password: &'static str| {
+        http.get(format!("http://127.0.0.1:{}/metrics", port))
+            .basic_auth("prom", Some(password))
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "5")
+            .send()
+    };
+    for _ in 0..100 {
+        if scrape("old").await.is_ok() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let before = (old.hits(), new.hits());
+    point_at(&new.url, "new", 1.0);
+    let _ = Command::new("kill")
+        .args(["-HUP", &child.id().to_string()])
+        .status();
+    // The rotated password and the new margin apply without a restart.
+    let mut reloaded = None;
+    for _ in 0..100 {
+        if let Ok(resp) = scrape("new").await {
+            if resp.status() == 200 && new.hits() > 0 {
+                reloaded = Some(resp.text().await.unwrap());
+                break;
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let old_password = scrape("old").await.map(|resp| resp.status().as_u16());
+    let still_running = child.try_wait().unwrap().is_none();
+    let _ = child.kill();
+    let stderr = String::from_utf8_lossy(&child.wait_with_output().unwrap().stderr).into_owned();
+    let _ = std::fs::remove_file(&config);
+
+    assert!(before.0 > 0 && before.1 == 0, "{:?}", before);
+    assert!(still_running);
+    let body = reloaded.expect("reloaded target scraped with the new password");
+    assert!(body.contains("i2pd_exporter_scrape_timeout_margin_seconds 1.0\n"));
+    assert_eq!(old_password.ok(), Some(401));
+    assert!(!stderr.contains("Reload ignores the changed"), "{}", stderr);
+}
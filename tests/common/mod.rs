@@ -0,0 +1,190 @@
+// Scripted raw-TCP JSON-RPC server for exercising the client against misbehaving routers.
+
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[derive(Clone)]
+pub enum MockReply {
+    Drop,              // Close the connection without answering
+    Http(u16, String), // Answer with this status and body
+    Chunked(String),   // Answer 200 with this body, chunked and without Content-Length
+    Dated(String),     // Answer 200 with this body and a `Date` header, as i2pd does
+    Hang(Duration),    // Read the request, then stall
+    // Answer a JSON-RPC batch, dated like `Dated`: each (call index, reply) gets that call's id.
+    Batch(Vec<(usize, serde_json::Value)>),
+}
+
+// Every connection gets its own request, so the request log doubles as a hit counter.
+struct Script {
+    replies: Vec<MockReply>,
+    requests: Mutex<Vec<String>>,
+}
+
+impl Script {
+    // Replies are consumed one per request; the last one repeats once the script runs out.
+    fn next(&self, request: String) -> MockReply {
+        let mut requests = self.requests.lock().unwrap();
+        let idx = requests.len().min(self.replies.len() - 1);
+        requests.push(request);
+        self.replies[idx].clone()
+    }
+}
+
+pub struct MockServer {
+    pub url: String, // I2PControl address as passed to --i2pcontrol-address
+    script: Arc<Script>,
+}
+
+impl MockServer {
+    pub async fn start(replies: Vec<MockReply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let script = Script::new(replies);
+        let shared = script.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_one(stream, shared.clone()));
+            }
+        });
+        MockServer { url, script }
+    }
+
+    // Same script served over a Unix domain socket.
+    #[cfg(unix)]
+    pub async fn start_unix(path: &std::path::Path, replies: Vec<MockReply>) -> Self {
+        let listener = tokio::net::UnixListener::bind(path).unwrap();
+        let url = format!("unix:{}", path.display());
+        let script = Script::new(replies);
+        let shared = script.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_one(stream, shared.clone()));
+            }
+        });
+        MockServer { url, script }
+    }
+
+    pub fn hits(&self) -> usize {
+        self.script.requests.lock().unwrap().len()
+    }
+
+    // Raw text (head and body) of every request received so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.script.requests.lock().unwrap().clone()
+    }
+}
+
+impl Script {
+    fn new(replies: Vec<MockReply>) -> Arc<Self> {
+        Arc::new(Script {
+            replies,
+            requests: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    while let Ok(n) = stream.read(&mut chunk).await {
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        let text = String::from_utf8_lossy(&buf);
+        if let Some(head_end) = text.find("\r\n\r\n") {
+            let len = text[..head_end]
+                .lines()
+                .find_map(|l| {
+                    let (k, v) = l.split_once(':')?;
+                    k.eq_ignore_ascii_case("content-length")
+                        .then(|| v.trim().parse::<usize>().ok())
+                        .flatten()
+                })
+                .unwrap_or(0);
+            if buf.len() >= head_end + 4 + len {
+                break;
+            }
+        }
+    }
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+async fn serve_one<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, script: Arc<Script>) {
+    let request = read_request(&mut stream).await;
+    match script.next(request.clone()) {
+        MockReply::Drop => {}
+        MockReply::Hang(d) => tokio::time::sleep(d).await,
+        MockReply::Http(status, body) => {
+            let resp = format!(
+                "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(resp.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+        MockReply::Batch(replies) => {
+            let body = batch_reply(&request_body(&request), &replies);
+            let resp = format!(
+                "HTTP/1.1 200 X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nDate: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                httpdate::fmt_http_date(std::time::SystemTime::now()),
+                body
+            );
+            let _ = stream.write_all(resp.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+        MockReply::Dated(body) => {
+            let resp = format!(
+                "HTTP/1.1 200 X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nDate: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                httpdate::fmt_http_date(std::time::SystemTime::now()),
+                body
+            );
+            let _ = stream.write_all(resp.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+        MockReply::Chunked(body) => {
+            let mut resp = "HTTP/1.1 200 X\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n".to_string();
+            for piece in body.as_bytes().chunks(64) {
+                resp.push_str(&format!("{:x}\r\n", piece.len()));
+                resp.push_str(&String::from_utf8_lossy(piece));
+                resp.push_str("\r\n");
+            }
+            resp.push_str("0\r\n\r\n");
+            let _ = stream.write_all(resp.as_bytes()).await;
+            let _ = stream.shutdown().await;
+        }
+    }
+}
+
+// Parsed JSON body of a raw request as recorded by `MockServer::requests`.
+pub fn request_body(request: &str) -> serde_json::Value {
+    let start = request.find("\r\n\r\n").map_or(request.len(), |i| i + 4);
+    serde_json::from_str(&request[start..]).unwrap_or_default()
+}
+
+// Batch reply body answering the calls in `calls` in the scripted order.
+fn batch_reply(calls: &serde_json::Value, replies: &[(usize, serde_json::Value)]) -> String {
+    let items = replies
+        .iter()
+        .map(|(call, reply)| {
+            let mut reply = reply.clone();
+            reply["id"] = calls[*call]["id"].clone();
+            reply
+        })
+        .collect();
+    serde_json::Value::Array(items).to_string()
+}
+
+// JSON-RPC success envelope around a RouterInfo result object.
+pub fn router_info_ok(result: serde_json::Value) -> String {
+    serde_json::json!({ "id": 1, "jsonrpc": "2.0", "result": result }).to_string()
+}
@@ -0,0 +1,630 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{request_body, router_info_ok, MockReply, MockServer};
+use i2pd_exporter::i2pcontrol::rpc::RpcCallError;
+use i2pd_exporter::i2pcontrol::{targets, ClientOptions, I2pControlClient};
+use i2pd_exporter::metrics::{encode_metrics_text, ExporterCounters, ScrapeStats};
+use serde_json::json;
+
+fn client(address: &str, rpc_retries: u32) -> I2pControlClient {
+    let settings = targets::HttpSettings {
+        unix_socket: targets::unix_socket_path(address),
+        ..Default::default()
+    };
+    client_with(address, &settings, rpc_retries)
+}
+
+fn client_with(
+    address: &str,
+    settings: &targets::HttpSettings,
+    rpc_retries: u32,
+) -> I2pControlClient {
+    let counters = ExporterCounters::default();
+    I2pControlClient::new(
+        targets::build_http_client(false, settings, &counters.connections_created).unwrap(),
+        targets::endpoint_url(address, targets::DEFAULT_JSONRPC_PATH),
+        ClientOptions {
+            max_scrape_timeout: Duration::from_secs(60),
+            rpc_retries,
+            ..ClientOptions::default()
+        },
+        counters,
+    )
+}
+
+#[tokio::test]
+async fn retries_dropped_connection_then_succeeds() {
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![MockReply::Drop, MockReply::Http(200, ok)]).await;
+
+    let client = client(&server.url, 2);
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .expect("retry should recover");
+
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+    // One dropped attempt, then one request per batch plus NetworkSetting.
+    assert_eq!(server.hits(), 4);
+    assert_eq!(client.counters().rpc_requests.get(), 4);
+    let body_len = router_info_ok(json!({ "i2p.router.version": "2.55.0" })).len() as u64;
+    assert_eq!(client.last_response_bytes(), 3 * body_len);
+    assert!(client.last_routerinfo_seconds().is_some());
+
+    // Only the two RouterInfo bodies are observed, not NetworkSetting.
+    assert_eq!(response_size_histogram(&client), (2, 2 * body_len));
+}
+
+// (count, sum) of i2pd_exporter_routerinfo_response_bytes.
+fn response_size_histogram(client: &I2pControlClient) -> (u64, u64) {
+    let stats = ScrapeStats {
+        counters: client.counters(),
+        ..ScrapeStats::default()
+    };
+    let text = encode_metrics_text(None, &stats, &i2pd_exporter::version::BUILD_INFO, &[]);
+    let value = |name: &str| {
+        text.lines()
+            .find_map(|l| l.strip_prefix(name)?.strip_prefix(' '))
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap() as u64
+    };
+    (
+        value("i2pd_exporter_routerinfo_response_bytes_count"),
+        value("i2pd_exporter_routerinfo_response_bytes_sum"),
+    )
+}
+
+#[tokio::test]
+async fn response_sizes_are_observed_once_per_routerinfo_body() {
+    // i2pd dates every reply, which also sets the clock offset.
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![MockReply::Dated(ok.clone())]).await;
+    let client = client(&server.url, 0);
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert!(data.clock_offset_seconds.is_some());
+    assert_eq!(response_size_histogram(&client), (2, 2 * ok.len() as u64));
+
+    // A batch is one body holding every call.
+    let server = MockServer::start(vec![MockReply::Batch(batch_results())]).await;
+    let client = batch_client(&server.url);
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert!(data.clock_offset_seconds.is_some());
+    assert_eq!(server.hits(), 1);
+    assert_eq!(
+        response_size_histogram(&client),
+        (1, client.last_response_bytes())
+    );
+}
+
+#[tokio::test]
+async fn each_call_gets_a_fresh_json_rpc_id() {
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+
+    let client = client(&server.url, 0);
+    for _ in 0..2 {
+        client
+            .fetch_router_info(Duration::from_secs(5))
+            .await
+            .unwrap();
+    }
+
+    let ids: Vec<u64> = server
+        .requests()
+        .iter()
+        .map(|r| {
+            let body: serde_json::Value =
+                serde_json::from_str(&r[r.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+            body["id"].as_u64().expect("numeric id")
+        })
+        .collect();
+    assert_eq!(ids.len(), 6);
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+}
+
+#[tokio::test]
+async fn counts_each_new_connection() {
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+
+    let client = client(&server.url, 0);
+    client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    // The mock closes every connection after one reply, so nothing can be reused.
+    assert_eq!(
+        client.counters().connections_created.get(),
+        server.hits() as u64
+    );
+}
+
+#[tokio::test]
+async fn drops_keys_the_router_rejects_as_invalid_params() {
+    let rejected = json!({
+        "id": 1, "jsonrpc": "2.0",
+        "error": { "code": -32602, "message": "Invalid params: unknown key i2p.router.netdb.bogus" }
+    });
+    let ok = router_info_ok(json!({ "i2p.router.netdb.knownpeers": 1234 }));
+    let server = MockServer::start(vec![
+        MockReply::Http(200, rejected.to_string()),
+        MockReply::Http(200, ok),
+    ])
+    .await;
+    let mut client = client(&server.url, 0);
+    client.options.router_info_keys = vec![
+        "i2p.router.netdb.knownpeers".to_string(),
+        "i2p.router.netdb.bogus".to_string(),
+    ];
+
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .expect("the remaining keys should still be fetched");
+    assert_eq!(data.netdb_knownpeers, Some(1234));
+
+    client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    let requests = server.requests();
+    assert!(requests[0].contains("i2p.router.netdb.bogus"));
+    // The retry and every later scrape leave the rejected key out.
+    assert!(requests[1..]
+        .iter()
+        .all(|r| !r.contains("i2p.router.netdb.bogus")));
+}
+
+#[tokio::test]
+async fn transport_failure_surfaces_without_retries() {
+    let server = MockServer::start(vec![MockReply::Drop]).await;
+
+    let err = client(&server.url, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        err.downcast_ref::<RpcCallError>(),
+        Some(RpcCallError::Transport(_))
+    ));
+    assert_eq!(server.hits(), 1);
+}
+
+#[tokio::test]
+async fn routerinfo_duration_unset_until_a_fetch_succeeds() {
+    let server = MockServer::start(vec![MockReply::Drop]).await;
+    let client = client(&server.url, 0);
+    assert!(client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .is_err());
+    assert_eq!(client.last_routerinfo_seconds(), None);
+}
+
+#[tokio::test]
+async fn rpc_error_is_not_retried() {
+    let body = json!({ "id": 1, "jsonrpc": "2.0", "error": { "code": -32600, "message": "bad" } });
+    let server = MockServer::start(vec![MockReply::Http(200, body.to_string())]).await;
+
+    let err = client(&server.url, 3)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        err.downcast_ref::<RpcCallError>(),
+        Some(RpcCallError::Rpc { .. })
+    ));
+    assert_eq!(server.hits(), 1);
+}
+
+#[tokio::test]
+async fn empty_replies_are_reported_as_such() {
+    for body in ["", "  \n", r#"{"id":1,"jsonrpc":"2.0"}"#] {
+        let server = MockServer::start(vec![MockReply::Http(200, body.to_string())]).await;
+        let err = client(&server.url, 2)
+            .fetch_router_info(Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<RpcCallError>(),
+                Some(RpcCallError::EmptyResponse { method }) if method == "RouterInfo"
+            ),
+            "{:?}: {}",
+            body,
+            err
+        );
+        assert_eq!(server.hits(), 1);
+    }
+}
+
+#[tokio::test]
+async fn oversized_responses_are_refused() {
+    let big = router_info_ok(json!({ "i2p.router.version": "x".repeat(4096) }));
+    for reply in [
+        MockReply::Http(200, big.clone()),
+        MockReply::Chunked(big.clone()),
+    ] {
+        let server = MockServer::start(vec![reply]).await;
+        let mut client = client(&server.url, 2);
+        client.options.max_response_bytes = 1024;
+
+        let err = client
+            .fetch_router_info(Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RpcCallError>(),
+            Some(RpcCallError::TooLarge { limit: 1024, .. })
+        ));
+        // Deterministic, so not retried.
+        assert_eq!(server.hits(), 1);
+    }
+
+    // Streamed bodies within the limit still decode.
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![MockReply::Chunked(ok)]).await;
+    let mut client = client(&server.url, 0);
+    client.options.max_response_bytes = 1024;
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+}
+
+#[tokio::test]
+async fn rpc_call_timeout_cuts_a_stalled_call_short_and_retries() {
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![
+        MockReply::Hang(Duration::from_secs(10)),
+        MockReply::Http(200, ok),
+    ])
+    .await;
+    let mut client = client(&server.url, 1);
+    client.options.rpc_call_timeout = Some(Duration::from_millis(200));
+
+    let started = Instant::now();
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .expect("retry after the capped call should succeed");
+
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn scrape_budget_still_bounds_calls_below_the_rpc_timeout() {
+    let server = MockServer::start(vec![MockReply::Hang(Duration::from_secs(10))]).await;
+    let mut client = client(&server.url, 1);
+    client.options.rpc_call_timeout = Some(Duration::from_secs(30));
+
+    let started = Instant::now();
+    let err = client
+        .fetch_router_info(Duration::from_millis(300))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        err.downcast_ref::<RpcCallError>(),
+        Some(RpcCallError::Transport(e)) if e.is_timeout()
+    ));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(server.hits(), 1);
+}
+
+#[tokio::test]
+async fn retries_stop_at_deadline() {
+    let server = MockServer::start(vec![MockReply::Drop]).await;
+
+    let started = std::time::Instant::now();
+    let result = client(&server.url, 10)
+        .fetch_router_info(Duration::from_millis(1500))
+        .await;
+
+    assert!(result.is_err());
+    // Backoffs of 100, 200 and 400ms fit; the 800ms step would overrun the budget.
+    assert!(started.elapsed() < Duration::from_millis(1500));
+    assert!(server.hits() <= 4);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn fetches_over_unix_socket() {
+    let path = std::env::temp_dir().join(format!("i2pd-exporter-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start_unix(&path, vec![MockReply::Http(200, ok)]).await;
+
+    let data = client(&server.url, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await;
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(data.unwrap().router_version.as_deref(), Some("2.55.0"));
+    assert_eq!(server.hits(), 3);
+}
+
+#[tokio::test]
+async fn http2_opt_in_still_talks_http1_to_plaintext_servers() {
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let settings = targets::HttpSettings {
+        http2: true,
+        ..Default::default()
+    };
+
+    let data = client_with(&server.url, &settings, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .expect("plain http falls back to HTTP/1.1 without prior knowledge");
+
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+}
+
+fn user_agent_of(request: &str) -> Option<&str> {
+    request.lines().find_map(|l| {
+        let (k, v) = l.split_once(':')?;
+        k.eq_ignore_ascii_case("user-agent").then(|| v.trim())
+    })
+}
+
+#[tokio::test]
+async fn sends_default_and_custom_user_agent() {
+    let ok = router_info_ok(json!({}));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+
+    client(&server.url, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    let settings = targets::HttpSettings {
+        user_agent: Some("exporter-b".to_string()),
+        ..Default::default()
+    };
+    client_with(&server.url, &settings, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    let requests = server.requests();
+    let default_ua = format!("i2pd-exporter/{}", i2pd_exporter::version::VERSION);
+    assert_eq!(user_agent_of(&requests[0]), Some(default_ua.as_str()));
+    assert_eq!(user_agent_of(&requests[3]), Some("exporter-b"));
+}
+
+#[tokio::test]
+async fn requests_only_configured_keys() {
+    let ok = router_info_ok(json!({ "i2p.router.uptime": 5000 }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+
+    let mut restricted = client(&server.url, 0);
+    restricted.options.router_info_keys = vec!["i2p.router.uptime".to_string()];
+    let data = restricted
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert_eq!(data.router_uptime, Some(5000));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].contains("\"i2p.router.uptime\""));
+    assert!(!requests[0].contains("i2p.router.version"));
+}
+
+#[tokio::test]
+async fn merges_network_setting_limits_read_with_nulls() {
+    let ri = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let ns = router_info_ok(json!({
+        "i2p.router.net.bw.in": "2048",
+        "i2p.router.net.bw.out": 1024,
+        "i2p.router.net.bw.share": "80"
+    }));
+    let server = MockServer::start(vec![
+        MockReply::Http(200, ri.clone()),
+        MockReply::Http(200, ri),
+        MockReply::Http(200, ns),
+    ])
+    .await;
+
+    let data = client(&server.url, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+    assert_eq!(data.net_bw_limit_in_kbps, Some(2048));
+    assert_eq!(data.net_bw_limit_out_kbps, Some(1024));
+    assert_eq!(data.net_bw_share_percent, Some(80.0));
+    // A non-null value would reconfigure the router.
+    let ns_request = &server.requests()[2];
+    assert!(ns_request.contains("\"method\":\"NetworkSetting\""));
+    assert!(ns_request.contains("\"i2p.router.net.bw.in\":null"));
+}
+
+#[tokio::test]
+async fn network_setting_failure_keeps_router_info() {
+    let ri = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![
+        MockReply::Http(200, ri.clone()),
+        MockReply::Http(200, ri),
+        MockReply::Http(500, "oops".to_string()),
+    ])
+    .await;
+
+    let data = client(&server.url, 0)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .expect("NetworkSetting errors are not fatal");
+
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+    assert_eq!(data.net_bw_limit_in_kbps, None);
+}
+
+// Successful replies to a full scrape's batch: two RouterInfo calls and NetworkSetting.
+fn batch_results() -> Vec<(usize, serde_json::Value)> {
+    vec![
+        (
+            0,
+            json!({ "jsonrpc": "2.0", "result": { "i2p.router.version": "2.55.0" } }),
+        ),
+        (
+            1,
+            json!({ "jsonrpc": "2.0", "result": { "i2p.router.netdb.knownpeers": 3000 } }),
+        ),
+        (
+            2,
+            json!({ "jsonrpc": "2.0", "result": { "i2p.router.net.bw.in": "2048" } }),
+        ),
+    ]
+}
+
+fn batch_client(address: &str) -> I2pControlClient {
+    let mut client = client(address, 0);
+    client.options.batch_rpc = true;
+    client
+}
+
+#[tokio::test]
+async fn batch_rpc_sends_every_call_in_one_request() {
+    // Replies arrive out of order; they are matched back to calls by id.
+    let mut results = batch_results();
+    results.reverse();
+    let server = MockServer::start(vec![MockReply::Batch(results)]).await;
+
+    let client = batch_client(&server.url);
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+    assert_eq!(data.netdb_knownpeers, Some(3000));
+    assert_eq!(data.net_bw_limit_in_kbps, Some(2048));
+    assert_eq!(server.hits(), 1);
+    assert_eq!(client.counters().rpc_requests.get(), 1);
+    assert!(client.last_response_bytes() > 0);
+    let calls = request_body(&server.requests()[0]);
+    let methods: Vec<&str> = calls
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["method"].as_str().unwrap())
+        .collect();
+    assert_eq!(methods, ["RouterInfo", "RouterInfo", "NetworkSetting"]);
+
+    // Batched calls draw from the same id sequence as single calls, so ids never repeat.
+    client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    let ids: Vec<u64> = server
+        .requests()
+        .iter()
+        .flat_map(|r| request_body(r).as_array().unwrap().clone())
+        .map(|call| call["id"].as_u64().expect("numeric id"))
+        .collect();
+    assert_eq!(ids.len(), 6);
+    assert!(ids.windows(2).all(|w| w[0] < w[1]), "{:?}", ids);
+}
+
+#[tokio::test]
+async fn batch_rpc_falls_back_to_sequential_when_unsupported() {
+    let rejected = json!({
+        "id": null,
+        "jsonrpc": "2.0",
+        "error": { "code": -32600, "message": "Invalid Request" }
+    })
+    .to_string();
+    let ok = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let server = MockServer::start(vec![
+        MockReply::Http(200, rejected),
+        MockReply::Http(200, ok),
+    ])
+    .await;
+
+    let client = batch_client(&server.url);
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .expect("sequential fallback should succeed");
+    assert_eq!(data.router_version.as_deref(), Some("2.55.0"));
+    // The rejected batch, then both RouterInfo calls and NetworkSetting.
+    assert_eq!(server.hits(), 4);
+
+    // Later scrapes skip the batch attempt entirely.
+    client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(server.hits(), 7);
+}
+
+#[tokio::test]
+async fn batch_rpc_surfaces_router_info_errors() {
+    let server = MockServer::start(vec![MockReply::Batch(vec![
+        (
+            0,
+            json!({ "jsonrpc": "2.0", "error": { "code": -32002, "message": "Token expired" } }),
+        ),
+        (1, json!({ "jsonrpc": "2.0", "result": {} })),
+        (2, json!({ "jsonrpc": "2.0", "result": {} })),
+    ])])
+    .await;
+
+    let err = batch_client(&server.url)
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap_err();
+
+    let err = err.downcast::<RpcCallError>().unwrap();
+    assert!(matches!(*err, RpcCallError::Rpc { code: -32002, .. }));
+    assert_eq!(server.hits(), 1);
+}
+
+#[tokio::test]
+async fn counts_router_version_changes_between_scrapes() {
+    let v1 = router_info_ok(json!({ "i2p.router.version": "2.55.0" }));
+    let v2 = router_info_ok(json!({ "i2p.router.version": "2.56.0" }));
+    // Each full fetch makes three calls (two RouterInfo batches and NetworkSetting).
+    let server = MockServer::start(vec![
+        MockReply::Http(200, v1.clone()),
+        MockReply::Http(200, v1.clone()),
+        MockReply::Http(200, v1),
+        MockReply::Http(200, v2),
+    ])
+    .await;
+    let client = client(&server.url, 0);
+    let changes = || client.counters().router_version_changes.get();
+
+    client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(changes(), 0);
+
+    let data = client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(data.router_version.as_deref(), Some("2.56.0"));
+    assert_eq!(changes(), 1);
+
+    client
+        .fetch_router_info(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(changes(), 1);
+}
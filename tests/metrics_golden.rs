@@ -1,42 +1,99 @@
+use i2pd_exporter::metrics::{encode_metrics_text, ScrapeStats};
+use i2pd_exporter::version::BuildInfo;
 use serde_json as json;
 
+// Fixed build labels keep the output independent of the checkout.
+const BUILD: BuildInfo = BuildInfo {
+    version: "0.0.0-test",
+    revision: "0123456789ab",
+    rustc_version: "1.0.0",
+    build_profile: "test",
+};
+
 #[test]
 fn routerinfo_full_golden() {
-    let json_str = include_str!("fixtures/routerinfo_full.json");
+    assert_matches_golden(include_str!("fixtures/routerinfo_full.json"));
+}
+
+// Some i2pd builds send bandwidth and network status as JSON strings; the output must not change.
+#[test]
+fn routerinfo_string_encoded_numbers_golden() {
+    assert_matches_golden(include_str!("fixtures/routerinfo_strings.json"));
+}
+
+fn assert_matches_golden(json_str: &str) {
     let expected_router_only = include_str!("fixtures/routerinfo_full.prom");
 
     let data: i2pd_exporter::i2pcontrol::types::RouterInfoResult =
         json::from_str(json_str).expect("valid RouterInfoResult JSON");
 
     // Generate full metrics text (router + exporter self-metrics)
-    let got = i2pd_exporter::metrics::encode_metrics_text(
-        Some(&data),
-        0.0,
-        None,
-        0,
-        i2pd_exporter::version::VERSION,
-    );
+    let got = encode_metrics_text(Some(&data), &ScrapeStats::default(), &BUILD, &[]);
 
     // Debug output for troubleshooting differences
     eprintln!("{}", got);
 
-    // Ensure every expected router metric line appears in the encoded output
+    // Ensure every expected router metric and UNIT line appears in the encoded output
     for line in expected_router_only
         .lines()
-        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter(|l| !l.trim().is_empty() && (!l.starts_with('#') || l.starts_with("# UNIT ")))
     {
-        let mut ok = got.contains(line);
-
-        // Accept trimmed float representation differences (e.g., 0.870000 vs 0.87)
-        if !ok {
-            if let Some((head, val)) = line.rsplit_once(' ') {
-                if let Ok(f) = val.parse::<f64>() {
-                    let alt = format!("{} {}", head, f);
-                    ok = ok || got.contains(&alt);
-                }
-            }
-        }
-
-        assert!(ok, "missing expected line in output: {}", line);
+        assert!(
+            got.lines().any(|l| l == line),
+            "missing expected line in output: {}",
+            line
+        );
+    }
+}
+
+#[test]
+fn counts_populated_routerinfo_fields() {
+    let full: i2pd_exporter::i2pcontrol::types::RouterInfoResult =
+        json::from_str(include_str!("fixtures/routerinfo_full.json")).unwrap();
+    let got = encode_metrics_text(Some(&full), &ScrapeStats::default(), &BUILD, &[]);
+    assert!(got.contains("i2pd_exporter_routerinfo_fields_present 29.0\n"));
+
+    let partial: i2pd_exporter::i2pcontrol::types::RouterInfoResult =
+        json::from_str(r#"{"i2p.router.status": "1", "i2p.router.uptime": 5}"#).unwrap();
+    assert_eq!(partial.fields_present(), 2);
+
+    // Without router data there is nothing to count.
+    let got = encode_metrics_text(None, &ScrapeStats::default(), &BUILD, &[]);
+    assert!(!got.contains("i2pd_exporter_routerinfo_fields_present"));
+}
+
+#[test]
+fn routerinfo_full_with_extra_labels() {
+    let json_str = include_str!("fixtures/routerinfo_full.json");
+    let data: i2pd_exporter::i2pcontrol::types::RouterInfoResult =
+        json::from_str(json_str).expect("valid RouterInfoResult JSON");
+    let extra_labels = vec![
+        ("instance".to_string(), "router-1".to_string()),
+        ("site".to_string(), "eu".to_string()),
+    ];
+
+    let got = encode_metrics_text(Some(&data), &ScrapeStats::default(), &BUILD, &extra_labels);
+
+    eprintln!("{}", got);
+
+    assert!(got.contains("i2p_router_status{instance=\"router-1\",site=\"eu\"} 1.0"));
+    assert!(got.contains(
+        "i2pd_exporter_build_info{instance=\"router-1\",site=\"eu\",version=\"0.0.0-test\",revision=\"0123456789ab\"} 1"
+    ));
+    assert!(got.contains(
+        "i2pd_exporter_info{instance=\"router-1\",site=\"eu\",version=\"0.0.0-test\",rustc_version=\"1.0.0\",build_profile=\"test\"} 1"
+    ));
+    assert!(got.contains(
+        "i2p_router_net_bytes_total{instance=\"router-1\",site=\"eu\",direction=\"inbound\"} 123456.0"
+    ));
+    assert!(got.contains("i2pd_exporter_last_scrape_error{instance=\"router-1\",site=\"eu\"} 0.0"));
+
+    // Every sample line carries both static labels
+    for line in got.lines().filter(|l| !l.starts_with('#')) {
+        assert!(
+            line.contains("instance=\"router-1\"") && line.contains("site=\"eu\""),
+            "missing extra labels on line: {}",
+            line
+        );
     }
 }
@@ -0,0 +1,197 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::{router_info_ok, MockReply, MockServer};
+use i2pd_exporter::i2pcontrol::{targets, ClientOptions, I2pControlClient, TargetClients};
+use i2pd_exporter::metrics::ExporterCounters;
+use i2pd_exporter::server::{routes, RouteOptions};
+use serde_json::json;
+
+fn state_for(address: &str) -> Arc<TargetClients> {
+    let settings = targets::HttpSettings::default();
+    let counters = ExporterCounters::default();
+    let default = Arc::new(I2pControlClient::new(
+        targets::build_http_client(false, &settings, &counters.connections_created).unwrap(),
+        targets::endpoint_url(address, targets::DEFAULT_JSONRPC_PATH),
+        ClientOptions {
+            max_scrape_timeout: Duration::from_secs(60),
+            cache_ttl: Duration::from_secs(60),
+            rpc_retries: 0,
+            ..ClientOptions::default()
+        },
+        counters,
+    ));
+    Arc::new(TargetClients::new(default, false, settings))
+}
+
+#[tokio::test]
+async fn lite_route_requests_only_status_and_bandwidth() {
+    let ok = router_info_ok(json!({
+        "i2p.router.status": "1",
+        "i2p.router.net.bw.inbound.15s": 1024.0,
+        "i2p.router.netdb.knownpeers": 3000
+    }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+
+    let resp = warp::test::request()
+        .path("/metrics-lite")
+        .header("X-Prometheus-Scrape-Timeout-Seconds", "5")
+        .reply(&filter)
+        .await;
+    assert_eq!(resp.status(), 200);
+    let body = std::str::from_utf8(resp.body()).unwrap();
+    assert!(body.contains("i2p_router_status 1"));
+
+    // One RouterInfo batch, no NetworkSetting, no NetDB keys.
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains("i2p.router.net.bw.inbound.15s"));
+    assert!(!requests[0].contains("i2p.router.netdb"));
+    assert!(!requests[0].contains("NetworkSetting"));
+}
+
+#[tokio::test]
+async fn lite_route_does_not_fill_the_full_cache() {
+    let ok = router_info_ok(json!({ "i2p.router.status": "1" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+
+    for path in ["/metrics-lite", "/metrics"] {
+        let resp = warp::test::request()
+            .path(path)
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "5")
+            .reply(&filter)
+            .await;
+        assert_eq!(resp.status(), 200);
+        let body = std::str::from_utf8(resp.body()).unwrap();
+        assert!(body.contains("i2pd_exporter_cache_hit 0"), "{}", path);
+    }
+    // Lite batch, then the full scrape's two batches and NetworkSetting.
+    assert_eq!(server.hits(), 4);
+}
+
+#[tokio::test]
+async fn ready_reflects_a_live_router_call() {
+    let ok = router_info_ok(json!({ "i2p.router.status": "1" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+
+    let resp = warp::test::request().path("/ready").reply(&filter).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.body().as_ref(), br#"{"status":"ready"}"#);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].contains("\"i2p.router.status\""));
+    assert!(!requests[0].contains("i2p.router.uptime"));
+}
+
+#[tokio::test]
+async fn ready_is_503_when_router_is_unreachable() {
+    let server = MockServer::start(vec![MockReply::Drop]).await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+
+    let resp = warp::test::request().path("/ready").reply(&filter).await;
+    assert_eq!(resp.status(), 503);
+    assert_eq!(resp.body().as_ref(), br#"{"status":"not_ready"}"#);
+}
+
+#[tokio::test]
+async fn concurrent_ready_checks_share_one_probe() {
+    let ok = router_info_ok(json!({ "i2p.router.status": "1" }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+
+    let probes: Vec<_> = (0..5)
+        .map(|_| {
+            let filter = filter.clone();
+            tokio::spawn(async move { warp::test::request().path("/ready").reply(&filter).await })
+        })
+        .collect();
+    for probe in probes {
+        assert_eq!(probe.await.unwrap().status(), 200);
+    }
+    assert_eq!(server.hits(), 1);
+}
+
+#[tokio::test]
+async fn debug_routerinfo_is_off_by_default() {
+    let server = MockServer::start(vec![MockReply::Http(200, router_info_ok(json!({})))]).await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+
+    let resp = warp::test::request()
+        .path("/debug/routerinfo")
+        .header("X-Prometheus-Scrape-Timeout-Seconds", "5")
+        .reply(&filter)
+        .await;
+    assert_eq!(resp.status(), 404);
+    assert_eq!(server.hits(), 0);
+}
+
+#[tokio::test]
+async fn debug_routerinfo_returns_merged_raw_json_redacted() {
+    let ok = router_info_ok(json!({
+        "i2p.router.version": "2.55.0",
+        "i2p.router.custom": [1, 2],
+        "Token": "secret"
+    }));
+    let server = MockServer::start(vec![MockReply::Http(200, ok)]).await;
+    let options = RouteOptions {
+        debug_endpoints: true,
+        ..RouteOptions::default()
+    };
+    let filter = routes(state_for(&server.url), options);
+
+    let resp = warp::test::request()
+        .path("/debug/routerinfo")
+        .header("X-Prometheus-Scrape-Timeout-Seconds", "5")
+        .reply(&filter)
+        .await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/json");
+    let body: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    assert_eq!(body["i2p.router.version"], "2.55.0");
+    assert_eq!(body["i2p.router.custom"], json!([1, 2]));
+    assert_eq!(body["Token"], "<redacted>");
+    // Both RouterInfo batches, no NetworkSetting.
+    assert_eq!(server.hits(), 2);
+
+    // Same timeout header requirement as /metrics.
+    let resp = warp::test::request()
+        .path("/debug/routerinfo")
+        .reply(&filter)
+        .await;
+    assert_eq!(resp.status(), 400);
+}
+
+#[tokio::test]
+async fn seconds_since_last_success_survives_a_failed_scrape() {
+    let ok = router_info_ok(json!({ "i2p.router.status": "1" }));
+    let server = MockServer::start(vec![
+        MockReply::Http(200, ok),
+        MockReply::Http(500, "down".to_string()),
+    ])
+    .await;
+    let filter = routes(state_for(&server.url), RouteOptions::default());
+    let scrape = || {
+        warp::test::request()
+            .path("/metrics-lite")
+            .header("X-Prometheus-Scrape-Timeout-Seconds", "5")
+            .reply(&filter)
+    };
+
+    let first = scrape().await;
+    assert_eq!(first.status(), 200);
+    let failed = scrape().await;
+    assert_eq!(failed.status(), 500);
+
+    let body = std::str::from_utf8(failed.body()).unwrap();
+    let line = body
+        .lines()
+        .find(|l| l.starts_with("i2pd_exporter_seconds_since_last_success "))
+        .expect("gauge present after a success");
+    let secs: f64 = line.rsplit_once(' ').unwrap().1.parse().unwrap();
+    assert!((0.0..5.0).contains(&secs));
+}